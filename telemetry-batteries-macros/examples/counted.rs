@@ -0,0 +1,15 @@
+use telemetry_batteries_macros::{counted, statsd};
+
+#[counted(name = "my_service.parse", labels("kind" => "example"))]
+#[tracing::instrument]
+fn parse(input: &str) -> eyre::Result<u32> {
+    Ok(input.parse()?)
+}
+
+#[statsd(prefix = "my_service")]
+#[tokio::main]
+pub async fn main() -> eyre::Result<()> {
+    parse("42")?;
+    let _ = parse("not a number");
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use telemetry_batteries_macros::datadog;
 
-#[datadog(service_name = "datadog-example")]
+#[datadog(service_name = env!("CARGO_PKG_NAME"))]
 #[tokio::main]
 pub async fn main() -> eyre::Result<()> {
     tracing::info!("foo");
@@ -0,0 +1,10 @@
+#[telemetry_batteries_macros::main(
+    datadog(service_name = "main-example"),
+    statsd(prefix = "main_example")
+)]
+#[tokio::main]
+pub async fn main() -> eyre::Result<()> {
+    tracing::info!("foo");
+    metrics::counter!("my_counter").increment(1);
+    Ok(())
+}
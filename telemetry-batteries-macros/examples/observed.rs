@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use telemetry_batteries_macros::{datadog, observed, statsd};
+
+#[observed(name = "my_service.fetch", labels("source" => "example"))]
+async fn fetch(fail: bool) -> eyre::Result<()> {
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    tracing::info!("fetching");
+
+    if fail {
+        eyre::bail!("fetch failed");
+    }
+
+    Ok(())
+}
+
+#[datadog(service_name = "observed-example")]
+#[statsd(prefix = "my_service")]
+#[tokio::main]
+pub async fn main() -> eyre::Result<()> {
+    fetch(false).await?;
+    let _ = fetch(true).await;
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+use telemetry_batteries_macros::{statsd, timed};
+
+#[timed(name = "my_service.work", labels("kind" => "example"))]
+async fn work() {
+    tokio::time::sleep(Duration::from_millis(10)).await;
+}
+
+#[statsd(prefix = "my_service")]
+#[tokio::main]
+pub async fn main() -> eyre::Result<()> {
+    work().await;
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use quote::{quote, ToTokens};
+use syn::{parse_quote, ItemFn, ReturnType, Signature, Type};
+
+/// Rewrites the body of `input_fn` to run the original body in its own async
+/// block or closure between `before` and `after`.
+///
+/// Early returns and `?` stay within the original body, so `after` always
+/// runs and can inspect its value, bound to `result`. The signature and
+/// attributes of `input_fn` are left untouched.
+pub(crate) fn wrap_body(
+    input_fn: &mut ItemFn,
+    before: proc_macro2::TokenStream,
+    after: proc_macro2::TokenStream,
+) -> syn::Result<()> {
+    wrap(input_fn, None, before, after)
+}
+
+/// Like [`wrap_body`], but the original body runs inside the tracing `span`,
+/// which is entered on every poll for async functions.
+pub(crate) fn wrap_body_in_span(
+    input_fn: &mut ItemFn,
+    span: proc_macro2::TokenStream,
+    before: proc_macro2::TokenStream,
+    after: proc_macro2::TokenStream,
+) -> syn::Result<()> {
+    wrap(input_fn, Some(span), before, after)
+}
+
+fn wrap(
+    input_fn: &mut ItemFn,
+    span: Option<proc_macro2::TokenStream>,
+    before: proc_macro2::TokenStream,
+    after: proc_macro2::TokenStream,
+) -> syn::Result<()> {
+    check_wrappable(&input_fn.sig)?;
+
+    let output = match &input_fn.sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ty) => quote!(#ty),
+    };
+
+    let input_block = &input_fn.block;
+    let body = match (input_fn.sig.asyncness.is_some(), span) {
+        (true, None) => quote!(async move #input_block.await),
+        (true, Some(span)) => quote! {
+            telemetry_batteries::reexports::tracing::Instrument::instrument(
+                async move #input_block,
+                #span,
+            )
+            .await
+        },
+        (false, None) => quote!((move || -> #output #input_block)()),
+        (false, Some(span)) => {
+            quote!(#span.in_scope(move || -> #output #input_block))
+        }
+    };
+
+    let new_block: syn::Block = parse_quote!({
+        #before
+
+        let result: #output = #body;
+
+        #after
+
+        result
+    });
+
+    *input_fn.block = new_block;
+
+    Ok(())
+}
+
+/// The original body ends up in a closure or async block bound to a variable
+/// of the return type, which rules out `const` functions and `impl Trait`
+/// return types.
+fn check_wrappable(sig: &Signature) -> syn::Result<()> {
+    if let Some(constness) = &sig.constness {
+        return Err(syn::Error::new_spanned(
+            constness,
+            "`const` functions are not supported",
+        ));
+    }
+
+    if let ReturnType::Type(_, ty) = &sig.output {
+        if let Type::ImplTrait(_) = ty.as_ref() {
+            return Err(syn::Error::new_spanned(
+                ty,
+                "functions returning `impl Trait` are not supported",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Errors unless `input_fn` is async, for setup that has to run inside the
+/// tokio runtime.
+pub(crate) fn require_async(
+    input_fn: &ItemFn,
+    attribute: &str,
+) -> syn::Result<()> {
+    if input_fn.sig.asyncness.is_some() {
+        return Ok(());
+    }
+
+    Err(syn::Error::new_spanned(
+        input_fn.sig.fn_token,
+        format!(
+            "`#[{attribute}]` requires an async function, place it above \
+             `#[tokio::main]`"
+        ),
+    ))
+}
+
+/// Whether the function returns a `Result`, going by the name of the type.
+pub(crate) fn returns_result(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let Type::Path(type_path) = ty.as_ref() else {
+        return false;
+    };
+
+    type_path
+        .path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Result")
+}
+
+/// Reports `err` while still emitting `input_fn` unchanged, so that the error
+/// is not followed by others about the function missing.
+pub(crate) fn error_with_fn(
+    err: syn::Error,
+    input_fn: &ItemFn,
+) -> proc_macro::TokenStream {
+    let mut tokens = err.to_compile_error();
+    input_fn.to_tokens(&mut tokens);
+
+    tokens.into()
+}
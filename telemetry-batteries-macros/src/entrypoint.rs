@@ -0,0 +1,106 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input, Ident, ItemFn,
+};
+
+use crate::codegen::{error_with_fn, require_async, returns_result, wrap_body};
+use crate::metrics::statsd::StatsdArgs;
+use crate::parse::{parse_args, separator, set_once, unknown_arg};
+use crate::tracing::datadog::DatadogArgs;
+
+struct MainArgs {
+    datadog: Option<DatadogArgs>,
+    statsd: Option<StatsdArgs>,
+}
+
+impl Parse for MainArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut datadog = None;
+        let mut statsd = None;
+
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            let content;
+            parenthesized!(content in input);
+            match ident.to_string().as_str() {
+                "datadog" => set_once(&mut datadog, &ident, content.parse()?)?,
+                "statsd" => set_once(&mut statsd, &ident, content.parse()?)?,
+                _ => return Err(unknown_arg(&ident, &["datadog", "statsd"])),
+            }
+
+            separator(input)?;
+        }
+
+        Ok(MainArgs { datadog, statsd })
+    }
+}
+
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+    let main_args = match parse_args::<MainArgs>(attr, &input_fn) {
+        Ok(args) => args,
+        Err(tokens) => return tokens,
+    };
+
+    // Traces go to stdout unless a Datadog agent is configured
+    let tracing_init = match &main_args.datadog {
+        Some(datadog_args) => {
+            if let Err(err) = require_async(&input_fn, "main") {
+                return error_with_fn(err, &input_fn);
+            }
+
+            datadog_args.init()
+        }
+        None => quote! {
+            telemetry_batteries::tracing::stdout::StdoutBattery::init()
+        },
+    };
+    let metrics_init = main_args
+        .statsd
+        .as_ref()
+        .map(|statsd_args| try_init(&input_fn, statsd_args.init(), "StatsD"));
+
+    if let Err(err) = wrap_main(
+        &mut input_fn,
+        quote! {
+            let _tracing_shutdown_handle = #tracing_init;
+            #metrics_init
+        },
+    ) {
+        return error_with_fn(err, &input_fn);
+    }
+
+    let expanded = quote! {
+        #input_fn
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Rewrites the body of `input_fn` to run `setup` first, so that everything
+/// bound in `setup` is dropped after all of the user's code, including on
+/// early returns.
+pub(crate) fn wrap_main(
+    input_fn: &mut ItemFn,
+    setup: proc_macro2::TokenStream,
+) -> syn::Result<()> {
+    wrap_body(input_fn, setup, quote!())
+}
+
+/// Statement running the fallible `init`, propagating its error with `?` if
+/// `input_fn` returns a `Result` and panicking otherwise.
+pub(crate) fn try_init(
+    input_fn: &ItemFn,
+    init: proc_macro2::TokenStream,
+    backend: &str,
+) -> proc_macro2::TokenStream {
+    if returns_result(&input_fn.sig.output) {
+        quote!(#init?;)
+    } else {
+        let message = format!("failed to initialize {backend}");
+        quote!(#init.expect(#message);)
+    }
+}
@@ -1,19 +1,56 @@
 use proc_macro::TokenStream;
 
+mod codegen;
+mod entrypoint;
 mod metrics;
+mod parse;
 mod tracing;
 
+/// Macro to initialize all configured telemetry backends around `main`
+///
+/// # Parameters
+///
+/// - `datadog(...)`: Optional, takes the same parameters as the [`datadog`](macro@datadog)
+///   macro. Traces are written to stdout if not specified.
+///
+/// - `statsd(...)`: Optional, takes the same parameters as the [`statsd`](macro@statsd) macro.
+///   No metrics backend is initialized if not specified.
+///
+/// # Usage
+///
+/// Apply it to the main function of your application, before `tokio::main` if `main` is
+/// asynchronous. The tracing shutdown handle is dropped after the body of `main` has run,
+/// including on early returns, so buffered spans are flushed last.
+///
+/// ```ignore
+/// #[telemetry_batteries_macros::main(
+///     datadog(service_name = "my-service"),
+///     statsd(prefix = "my_service"),
+/// )]
+/// #[tokio::main]
+/// async fn main() -> eyre::Result<()> {
+///     Ok(())
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    entrypoint::main(attr, item)
+}
+
 /// Macro to initialize Datadog instrumentation
 ///
 /// # Parameters
 ///
-/// - `service_name`: Required string literal that specifies the name of the service.
+/// - `service_name`: Required `&str` or `String` that specifies the name of the service.
 ///
-/// - `endpoint`: Optional string literal that specifies the Datadog agent's endpoint
+/// - `endpoint`: Optional `&str` or `String` that specifies the Datadog agent's endpoint
 ///   to which telemetry data will be sent. If not specified, this value defaults to http://localhost:8126.
 ///
 /// - `location`: Optional boolean indicates whether to include the location in traces. Defaults to `false` if not specified.
 ///
+/// Parameters can be any expression, e.g. `env!("CARGO_PKG_NAME")` or a function call, and are
+/// evaluated at runtime before the body of `main`.
+///
 /// # Usage
 ///
 /// To use the `datadog` macro, apply it to the main function
@@ -29,7 +66,7 @@ pub fn datadog(attr: TokenStream, item: TokenStream) -> TokenStream {
 ///
 /// # Parameters
 ///
-/// - `host`: Optional string literal specifying the StatsD server's IP. Defaults to `"localhost"` if not provided.
+/// - `host`: Optional `&str` or `String` specifying the StatsD server's IP. Defaults to `"localhost"` if not provided.
 ///
 /// - `port`: Optional u16 specifying the port on which the StatsD server is listening.  Defaults to `8125` if not provided.
 ///
@@ -38,14 +75,124 @@ pub fn datadog(attr: TokenStream, item: TokenStream) -> TokenStream {
 /// - `queue_size`: Optional usize specifying the size of the queue for storing metrics
 ///   before sending to the server. Defaults to 5000 if not provided.
 ///
-/// - `prefix`: Optional string literal used as a prefix for all metrics sent. No prefix is added if not provided.
+/// - `prefix`: Optional `&str` or `String` used as a prefix for all metrics sent. No prefix is added if not provided.
+///
+/// Parameters can be any expression and are evaluated at runtime before the body of `main`.
+///
+/// If initialization fails, the error is returned with `?` when `main` returns a `Result`, and
+/// `main` panics otherwise.
 ///
 /// # Usage
 ///
 /// To use the `statsd` macro, apply it to the main function
-/// of your application. Due to how the `StatsdBattery` from `telemetry-batteries` is configured
-/// the `main` function must be asynchronous and use the `tokio::main` macro after the `statsd` macro.
+/// of your application. `main` may be synchronous, or asynchronous with the `tokio::main` macro
+/// after the `statsd` macro.
 #[proc_macro_attribute]
 pub fn statsd(attr: TokenStream, item: TokenStream) -> TokenStream {
     metrics::statsd::statsd(attr, item)
 }
+
+/// Macro recording the duration of every call to a function
+///
+/// # Parameters
+///
+/// - `name`: Optional string literal, the histogram is named `<name>.duration`. Defaults to the
+///   name of the function.
+///
+/// - `labels`: Optional labels added to the histogram, in the `"key" => value` form taken by the
+///   `metrics` macros.
+///
+/// # Usage
+///
+/// Apply it to any function, sync or async. The duration is recorded in seconds when the function
+/// returns, or when the future of an async function completes or is dropped.
+///
+/// ```ignore
+/// #[timed(name = "db.query", labels("table" => "users"))]
+/// async fn fetch_user(id: u64) -> eyre::Result<User> {
+///     // ...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn timed(attr: TokenStream, item: TokenStream) -> TokenStream {
+    metrics::timed::timed(attr, item)
+}
+
+/// Macro counting the calls to a function and the errors it returns
+///
+/// # Parameters
+///
+/// - `name`: Optional string literal, the counters are named `<name>.calls` and `<name>.errors`.
+///   Defaults to the name of the function.
+///
+/// - `labels`: Optional labels added to both counters, in the `"key" => value` form taken by the
+///   `metrics` macros.
+///
+/// # Usage
+///
+/// Apply it to any function, sync or async. `<name>.errors` is only recorded for functions
+/// returning a `Result`, when they return `Err`. It can be combined with `#[tracing::instrument]`
+/// in either order.
+///
+/// ```ignore
+/// #[counted(name = "db.query")]
+/// #[tracing::instrument]
+/// async fn fetch_user(id: u64) -> eyre::Result<User> {
+///     // ...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn counted(attr: TokenStream, item: TokenStream) -> TokenStream {
+    metrics::counted::counted(attr, item)
+}
+
+/// Macro instrumenting a function with a tracing span and call metrics
+///
+/// # Parameters
+///
+/// - `name`: Optional string literal naming the span. The metrics are named `<name>.duration`,
+///   `<name>.calls` and `<name>.errors`. Defaults to the name of the function.
+///
+/// - `labels`: Optional labels added to the metrics, in the `"key" => value` form taken by the
+///   `metrics` macros.
+///
+/// # Usage
+///
+/// Apply it to any function, sync or async. It records the same metrics as `#[timed]` and
+/// `#[counted]` combined, and runs the body in an info-level span.
+///
+/// ```ignore
+/// #[observed(name = "db.query", labels("table" => "users"))]
+/// async fn fetch_user(id: u64) -> eyre::Result<User> {
+///     // ...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn observed(attr: TokenStream, item: TokenStream) -> TokenStream {
+    metrics::observed::observed(attr, item)
+}
+
+/// Macro installing a test subscriber for the duration of a test
+///
+/// # Parameters
+///
+/// - `pretty`: Optional flag writing pretty-printed events to stderr for every test. By default
+///   events go through libtest's output capture and are only shown for failing tests.
+///
+/// # Usage
+///
+/// Apply it before `test` or `tokio::test`. The subscriber is only installed for the current
+/// thread, so tests running in parallel don't conflict, and the previous subscriber is restored
+/// when the test ends. Requires the `testing` feature of `telemetry-batteries`.
+///
+/// ```ignore
+/// #[traced_test]
+/// #[tokio::test]
+/// async fn test_handler() {
+///     tracing::info!("shown if the test fails");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn traced_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    tracing::traced_test::traced_test(attr, item)
+}
@@ -1 +1,5 @@
+mod args;
+pub mod counted;
+pub mod observed;
 pub mod statsd;
+pub mod timed;
@@ -0,0 +1,76 @@
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    Expr, Ident, LitStr, Token,
+};
+
+use crate::parse::{separator, set_once, unknown_arg};
+
+/// Arguments shared by the function metrics macros.
+pub(crate) struct MetricArgs {
+    name: Option<LitStr>,
+    labels: Vec<(LitStr, Expr)>,
+}
+
+impl Parse for MetricArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut name = None;
+        let mut labels = Vec::new();
+        let mut labels_seen = None;
+
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            match ident.to_string().as_str() {
+                "name" => {
+                    let _: Token![=] = input.parse()?;
+                    set_once(&mut name, &ident, input.parse()?)?;
+                }
+                "labels" => {
+                    set_once(&mut labels_seen, &ident, ())?;
+
+                    let content;
+                    parenthesized!(content in input);
+                    while !content.is_empty() {
+                        let key: LitStr = content.parse()?;
+                        let _: Token![=>] = content.parse()?;
+                        labels.push((key, content.parse()?));
+
+                        separator(&content)?;
+                    }
+                }
+                _ => return Err(unknown_arg(&ident, &["name", "labels"])),
+            }
+
+            separator(input)?;
+        }
+
+        Ok(MetricArgs { name, labels })
+    }
+}
+
+impl MetricArgs {
+    /// The metric name, `<name>.<suffix>`.
+    pub(crate) fn metric_name(&self, function: &Ident, suffix: &str) -> LitStr {
+        let name = self.name(function).value();
+
+        LitStr::new(&format!("{name}.{suffix}"), function.span())
+    }
+
+    /// The `name` argument, defaulting to the name of the annotated function.
+    pub(crate) fn name(&self, function: &Ident) -> LitStr {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => LitStr::new(&function.to_string(), function.span()),
+        }
+    }
+
+    /// Labels in the `key => value` form taken by the `metrics` macros.
+    pub(crate) fn labels(&self) -> proc_macro2::TokenStream {
+        let labels = self
+            .labels
+            .iter()
+            .map(|(key, value)| quote::quote!(#key => #value));
+
+        quote::quote!(#(, #labels)*)
+    }
+}
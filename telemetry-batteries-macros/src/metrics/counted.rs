@@ -0,0 +1,51 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, ItemFn};
+
+use super::args::MetricArgs;
+use crate::codegen::{error_with_fn, returns_result, wrap_body};
+use crate::parse::parse_args;
+
+pub fn counted(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+    let metric_args = match parse_args::<MetricArgs>(attr, &input_fn) {
+        Ok(args) => args,
+        Err(tokens) => return tokens,
+    };
+
+    let calls = metric_args.metric_name(&input_fn.sig.ident, "calls");
+    let errors = metric_args.metric_name(&input_fn.sig.ident, "errors");
+    let labels = metric_args.labels();
+
+    let count_call = quote! {
+        telemetry_batteries::reexports::metrics::counter!(#calls #labels)
+            .increment(1);
+    };
+
+    // Errors can only be told apart by looking at the returned value, which
+    // needs the body wrapped.
+    if returns_result(&input_fn.sig.output) {
+        if let Err(err) = wrap_body(
+            &mut input_fn,
+            count_call,
+            quote! {
+                if result.is_err() {
+                    telemetry_batteries::reexports::metrics::counter!(
+                        #errors #labels
+                    )
+                    .increment(1);
+                }
+            },
+        ) {
+            return error_with_fn(err, &input_fn);
+        }
+    } else {
+        input_fn.block.stmts.insert(0, parse_quote!(#count_call));
+    }
+
+    let expanded = quote! {
+        #input_fn
+    };
+
+    TokenStream::from(expanded)
+}
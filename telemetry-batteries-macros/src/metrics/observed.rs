@@ -0,0 +1,61 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, ItemFn};
+
+use super::args::MetricArgs;
+use crate::codegen::{error_with_fn, returns_result, wrap_body_in_span};
+use crate::parse::parse_args;
+
+pub fn observed(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+    let metric_args = match parse_args::<MetricArgs>(attr, &input_fn) {
+        Ok(args) => args,
+        Err(tokens) => return tokens,
+    };
+
+    // The span and the metrics share the same name, so traces and
+    // dashboards line up.
+    let name = metric_args.name(&input_fn.sig.ident);
+    let duration = metric_args.metric_name(&input_fn.sig.ident, "duration");
+    let calls = metric_args.metric_name(&input_fn.sig.ident, "calls");
+    let errors = metric_args.metric_name(&input_fn.sig.ident, "errors");
+    let labels = metric_args.labels();
+
+    let count_error = returns_result(&input_fn.sig.output).then(|| {
+        quote! {
+            if result.is_err() {
+                telemetry_batteries::reexports::metrics::counter!(
+                    #errors #labels
+                )
+                .increment(1);
+            }
+        }
+    });
+
+    if let Err(err) = wrap_body_in_span(
+        &mut input_fn,
+        quote!(telemetry_batteries::reexports::tracing::info_span!(#name)),
+        quote! {
+            telemetry_batteries::reexports::metrics::counter!(#calls #labels)
+                .increment(1);
+            let __telemetry_batteries_timer =
+                telemetry_batteries::metrics::timer::Timer::new(
+                    telemetry_batteries::reexports::metrics::histogram!(
+                        #duration #labels
+                    ),
+                );
+        },
+        quote! {
+            ::core::mem::drop(__telemetry_batteries_timer);
+            #count_error
+        },
+    ) {
+        return error_with_fn(err, &input_fn);
+    }
+
+    let expanded = quote! {
+        #input_fn
+    };
+
+    TokenStream::from(expanded)
+}
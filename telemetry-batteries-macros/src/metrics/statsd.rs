@@ -2,7 +2,13 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
     parse::{Parse, ParseStream},
-    parse_macro_input, parse_quote, Ident, ItemFn, LitStr, Token,
+    parse_macro_input, Expr, Ident, ItemFn, Token,
+};
+
+use crate::codegen::error_with_fn;
+use crate::entrypoint::{try_init, wrap_main};
+use crate::parse::{
+    int_arg, parse_args, separator, set_once, str_arg, unknown_arg,
 };
 
 pub const DEFAULT_HOST_ENDPOINT: &str = "localhost";
@@ -10,12 +16,15 @@ pub const DEFAULT_HOST_PORT: u16 = 8125;
 pub const DEFAULT_BUFFER_SIZE: usize = 256;
 pub const DEFAULT_QUEUE_SIZE: usize = 5000;
 
-struct StatsdArgs {
-    host: Option<String>,
-    port: Option<u16>,
-    queue_size: Option<usize>,
-    buffer_size: Option<usize>,
-    prefix: Option<String>,
+const STATSD_ARGS: &[&str] =
+    &["host", "port", "queue_size", "buffer_size", "prefix"];
+
+pub(crate) struct StatsdArgs {
+    host: Option<Expr>,
+    port: Option<Expr>,
+    queue_size: Option<Expr>,
+    buffer_size: Option<Expr>,
+    prefix: Option<Expr>,
 }
 
 impl Parse for StatsdArgs {
@@ -30,42 +39,27 @@ impl Parse for StatsdArgs {
             let ident: Ident = input.parse()?;
             let _: Token![=] = input.parse()?;
             match ident.to_string().as_str() {
-                "host" => {
-                    if let Ok(lit_str) = input.parse::<LitStr>() {
-                        host = Some(lit_str.value());
-                    }
-                }
+                "host" => set_once(&mut host, &ident, str_arg(input, &ident)?)?,
                 "port" => {
-                    if let Ok(lit_int) = input.parse::<syn::LitInt>() {
-                        port = Some(lit_int.base10_parse::<u16>()?);
-                    }
-                }
-                "queue_size" => {
-                    if let Ok(lit_int) = input.parse::<syn::LitInt>() {
-                        queue_size = Some(lit_int.base10_parse::<usize>()?);
-                    }
-                }
-                "buffer_size" => {
-                    if let Ok(lit_int) = input.parse::<syn::LitInt>() {
-                        buffer_size = Some(lit_int.base10_parse::<usize>()?);
-                    }
+                    set_once(&mut port, &ident, int_arg::<u16>(input, &ident)?)?
                 }
+                "queue_size" => set_once(
+                    &mut queue_size,
+                    &ident,
+                    int_arg::<usize>(input, &ident)?,
+                )?,
+                "buffer_size" => set_once(
+                    &mut buffer_size,
+                    &ident,
+                    int_arg::<usize>(input, &ident)?,
+                )?,
                 "prefix" => {
-                    if let Ok(lit_str) = input.parse::<LitStr>() {
-                        prefix = Some(lit_str.value());
-                    }
-                }
-                _ => {
-                    return Err(syn::Error::new(
-                        ident.span(),
-                        "Unexpected argument",
-                    ))
+                    set_once(&mut prefix, &ident, str_arg(input, &ident)?)?
                 }
+                _ => return Err(unknown_arg(&ident, STATSD_ARGS)),
             }
 
-            if !input.is_empty() {
-                let _: Option<Token![,]> = input.parse()?;
-            }
+            separator(input)?;
         }
 
         Ok(StatsdArgs {
@@ -78,35 +72,59 @@ impl Parse for StatsdArgs {
     }
 }
 
-pub fn statsd(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let statsd_args = parse_macro_input!(attr as StatsdArgs);
-    let mut input_fn = parse_macro_input!(item as ItemFn);
+impl StatsdArgs {
+    /// Expression initializing the StatsD battery, evaluating to its
+    /// `Result`.
+    pub(crate) fn init(&self) -> proc_macro2::TokenStream {
+        // Use provided values or defaults
+        let host = match &self.host {
+            Some(host) => quote!(#host),
+            None => quote!(#DEFAULT_HOST_ENDPOINT),
+        };
+        let port = match &self.port {
+            Some(port) => quote!(#port),
+            None => quote!(#DEFAULT_HOST_PORT),
+        };
+        let queue_size = match &self.queue_size {
+            Some(queue_size) => quote!(#queue_size),
+            None => quote!(#DEFAULT_QUEUE_SIZE),
+        };
+        let buffer_size = match &self.buffer_size {
+            Some(buffer_size) => quote!(#buffer_size),
+            None => quote!(#DEFAULT_BUFFER_SIZE),
+        };
+        let prefix = match &self.prefix {
+            Some(prefix) => quote! {
+                Some(::std::convert::AsRef::<str>::as_ref(&#prefix))
+            },
+            None => quote!(None),
+        };
 
-    // Use provided values or defaults
-    let host = statsd_args
-        .host
-        .unwrap_or_else(|| DEFAULT_HOST_ENDPOINT.to_string());
-    let port = statsd_args.port.unwrap_or(DEFAULT_HOST_PORT);
-    let queue_size = statsd_args.queue_size.unwrap_or(DEFAULT_QUEUE_SIZE);
-    let buffer_size = statsd_args.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
-    let prefix = statsd_args.prefix.unwrap_or_default();
+        quote! {{
+            let host = #host;
 
-    let input_block = &input_fn.block;
-    let new_block: syn::Block = parse_quote!({
-        let host = #host;
-        let prefix = #prefix;
-        telemetry_batteries::metrics::statsd::StatsdBattery::init(
-            &host,
-            #port,
-            #queue_size,
-            #buffer_size,
-            Some(&prefix),
-        )?;
+            telemetry_batteries::metrics::statsd::StatsdBattery::init(
+                ::std::convert::AsRef::<str>::as_ref(&host),
+                #port,
+                #queue_size,
+                #buffer_size,
+                #prefix,
+            )
+        }}
+    }
+}
 
-        #input_block
-    });
+pub fn statsd(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+    let statsd_args = match parse_args::<StatsdArgs>(attr, &input_fn) {
+        Ok(args) => args,
+        Err(tokens) => return tokens,
+    };
 
-    input_fn.block = Box::new(new_block);
+    let init = try_init(&input_fn, statsd_args.init(), "StatsD");
+    if let Err(err) = wrap_main(&mut input_fn, init) {
+        return error_with_fn(err, &input_fn);
+    }
 
     let expanded = quote! {
         #input_fn
@@ -0,0 +1,37 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, ItemFn};
+
+use super::args::MetricArgs;
+use crate::parse::parse_args;
+
+pub fn timed(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+    let metric_args = match parse_args::<MetricArgs>(attr, &input_fn) {
+        Ok(args) => args,
+        Err(tokens) => return tokens,
+    };
+
+    let name = metric_args.metric_name(&input_fn.sig.ident, "duration");
+    let labels = metric_args.labels();
+
+    // The timer is dropped when the function returns, on every path, or
+    // when the future of an async function completes or is dropped.
+    input_fn.block.stmts.insert(
+        0,
+        parse_quote! {
+            let __telemetry_batteries_timer =
+                telemetry_batteries::metrics::timer::Timer::new(
+                    telemetry_batteries::reexports::metrics::histogram!(
+                        #name #labels
+                    ),
+                );
+        },
+    );
+
+    let expanded = quote! {
+        #input_fn
+    };
+
+    TokenStream::from(expanded)
+}
@@ -0,0 +1,117 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use proc_macro::TokenStream;
+use syn::parse::{Parse, ParseStream};
+use syn::{Expr, ExprLit, Ident, ItemFn, Lit, Token};
+
+use crate::codegen::error_with_fn;
+
+/// Parses the attribute arguments of `input_fn`.
+///
+/// On error the function is emitted unchanged next to the error, so the
+/// compiler doesn't also complain about it missing.
+pub(crate) fn parse_args<T: Parse>(
+    attr: TokenStream,
+    input_fn: &ItemFn,
+) -> Result<T, TokenStream> {
+    syn::parse(attr).map_err(|err| error_with_fn(err, input_fn))
+}
+
+/// Parses the value of the string argument `name`.
+///
+/// Any expression is accepted and type checked by the compiler, literals of
+/// the wrong kind are rejected here with an error pointing at them.
+pub(crate) fn str_arg(input: ParseStream, name: &Ident) -> syn::Result<Expr> {
+    let expr: Expr = input.parse()?;
+
+    match literal(&expr) {
+        Some(Lit::Str(_)) | None => Ok(expr),
+        Some(lit) => Err(syn::Error::new_spanned(
+            lit,
+            format!("expected a string for `{name}`"),
+        )),
+    }
+}
+
+/// Parses the value of the integer argument `name`, checking that literals
+/// fit in `N`.
+pub(crate) fn int_arg<N>(input: ParseStream, name: &Ident) -> syn::Result<Expr>
+where
+    N: FromStr,
+    N::Err: Display,
+{
+    let expr: Expr = input.parse()?;
+
+    match literal(&expr) {
+        Some(Lit::Int(lit_int)) => {
+            lit_int.base10_parse::<N>()?;
+            Ok(expr)
+        }
+        None => Ok(expr),
+        Some(lit) => Err(syn::Error::new_spanned(
+            lit,
+            format!("expected an integer for `{name}`"),
+        )),
+    }
+}
+
+/// Parses the value of the boolean argument `name`.
+pub(crate) fn bool_arg(input: ParseStream, name: &Ident) -> syn::Result<Expr> {
+    let expr: Expr = input.parse()?;
+
+    match literal(&expr) {
+        Some(Lit::Bool(_)) | None => Ok(expr),
+        Some(lit) => Err(syn::Error::new_spanned(
+            lit,
+            format!("expected a boolean for `{name}`"),
+        )),
+    }
+}
+
+/// Stores the value of argument `name`, rejecting duplicates.
+pub(crate) fn set_once<T>(
+    slot: &mut Option<T>,
+    name: &Ident,
+    value: T,
+) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(syn::Error::new(
+            name.span(),
+            format!("duplicate argument `{name}`"),
+        ));
+    }
+
+    *slot = Some(value);
+
+    Ok(())
+}
+
+pub(crate) fn unknown_arg(name: &Ident, expected: &[&str]) -> syn::Error {
+    let expected = expected
+        .iter()
+        .map(|arg| format!("`{arg}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    syn::Error::new(
+        name.span(),
+        format!("unknown argument `{name}`, expected one of {expected}"),
+    )
+}
+
+/// Parses the comma separating arguments, unless the input is exhausted.
+pub(crate) fn separator(input: ParseStream) -> syn::Result<()> {
+    if !input.is_empty() {
+        let _: Token![,] = input.parse()?;
+    }
+
+    Ok(())
+}
+
+fn literal(expr: &Expr) -> Option<&Lit> {
+    match expr {
+        Expr::Lit(ExprLit { lit, .. }) => Some(lit),
+        _ => None,
+    }
+}
@@ -1 +1,2 @@
 pub mod datadog;
+pub mod traced_test;
@@ -2,15 +2,23 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
     parse::{Parse, ParseStream},
-    parse_macro_input, parse_quote, Ident, ItemFn, LitBool, LitStr, Token,
+    parse_macro_input, Expr, Ident, ItemFn, Token,
+};
+
+use crate::codegen::{error_with_fn, require_async};
+use crate::entrypoint::wrap_main;
+use crate::parse::{
+    bool_arg, parse_args, separator, set_once, str_arg, unknown_arg,
 };
 
 pub const DEFAULT_DATADOG_AGENT_ENDPOINT: &str = "http://localhost:8126";
 
-struct DatadogArgs {
-    endpoint: Option<String>,
-    service_name: String,
-    location: Option<bool>,
+const DATADOG_ARGS: &[&str] = &["endpoint", "service_name", "location"];
+
+pub(crate) struct DatadogArgs {
+    endpoint: Option<Expr>,
+    service_name: Expr,
+    location: Option<Expr>,
 }
 
 impl Parse for DatadogArgs {
@@ -24,31 +32,20 @@ impl Parse for DatadogArgs {
             let _: Token![=] = input.parse()?;
             match ident.to_string().as_str() {
                 "endpoint" => {
-                    if let Ok(lit_str) = input.parse::<LitStr>() {
-                        endpoint = Some(lit_str.value());
-                    }
-                }
-                "service_name" => {
-                    if let Ok(lit_str) = input.parse::<LitStr>() {
-                        service_name = Some(lit_str.value());
-                    }
+                    set_once(&mut endpoint, &ident, str_arg(input, &ident)?)?
                 }
+                "service_name" => set_once(
+                    &mut service_name,
+                    &ident,
+                    str_arg(input, &ident)?,
+                )?,
                 "location" => {
-                    if let Ok(lit_bool) = input.parse::<LitBool>() {
-                        location = Some(lit_bool.value());
-                    }
-                }
-                _ => {
-                    return Err(syn::Error::new(
-                        ident.span(),
-                        "Unexpected argument",
-                    ))
+                    set_once(&mut location, &ident, bool_arg(input, &ident)?)?
                 }
+                _ => return Err(unknown_arg(&ident, DATADOG_ARGS)),
             }
 
-            if !input.is_empty() {
-                let _comma: Option<Token![,]> = input.parse()?;
-            }
+            separator(input)?;
         }
 
         // Ensure service_name was provided
@@ -67,33 +64,58 @@ impl Parse for DatadogArgs {
     }
 }
 
+impl DatadogArgs {
+    /// Expression initializing the Datadog battery, evaluating to its
+    /// shutdown handle.
+    pub(crate) fn init(&self) -> proc_macro2::TokenStream {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => quote!(#endpoint),
+            None => quote!(#DEFAULT_DATADOG_AGENT_ENDPOINT),
+        };
+        let service_name = &self.service_name;
+        let location = match &self.location {
+            Some(location) => quote!(#location),
+            None => quote!(false),
+        };
+
+        // The arguments are evaluated at runtime, so they can be any
+        // expression evaluating to a `&str` or `String` (and `bool` for
+        // `location`).
+        quote! {{
+            let endpoint = #endpoint;
+            let service_name = #service_name;
+
+            telemetry_batteries::tracing::datadog::DatadogBattery::init(
+                Some(::std::convert::AsRef::<str>::as_ref(&endpoint)),
+                ::std::convert::AsRef::<str>::as_ref(&service_name),
+                None,
+                #location,
+            )
+        }}
+    }
+}
+
 pub fn datadog(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let datadog_args = parse_macro_input!(attr as DatadogArgs);
     let mut input_fn = parse_macro_input!(item as ItemFn);
+    let datadog_args = match parse_args::<DatadogArgs>(attr, &input_fn) {
+        Ok(args) => args,
+        Err(tokens) => return tokens,
+    };
 
-    let endpoint: String = datadog_args
-        .endpoint
-        .unwrap_or(DEFAULT_DATADOG_AGENT_ENDPOINT.to_string());
-
-    let service_name = datadog_args.service_name.as_str();
-    let location = datadog_args.location.unwrap_or(false);
-
-    let input_block = &input_fn.block;
-    let new_block: syn::Block = parse_quote!({
-        let endpoint = #endpoint;
-        let _tracing_shutdown_handle = telemetry_batteries::tracing::datadog::DatadogBattery::init(
-            Some(&endpoint),
-            #service_name,
-            None,
-            #location,
-        );
-
-
-
-        #input_block
-    });
+    // The batch exporter is spawned on the tokio runtime
+    if let Err(err) = require_async(&input_fn, "datadog") {
+        return error_with_fn(err, &input_fn);
+    }
 
-    input_fn.block = Box::new(new_block);
+    let init = datadog_args.init();
+    if let Err(err) = wrap_main(
+        &mut input_fn,
+        quote! {
+            let _tracing_shutdown_handle = #init;
+        },
+    ) {
+        return error_with_fn(err, &input_fn);
+    }
 
     let expanded = quote! {
         #input_fn
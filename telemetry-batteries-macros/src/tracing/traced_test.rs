@@ -0,0 +1,60 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote, Ident, ItemFn,
+};
+
+use crate::parse::{parse_args, unknown_arg};
+
+struct TracedTestArgs {
+    pretty: bool,
+}
+
+impl Parse for TracedTestArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut pretty = false;
+
+        if !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            match ident.to_string().as_str() {
+                "pretty" => pretty = true,
+                _ => return Err(unknown_arg(&ident, &["pretty"])),
+            }
+        }
+
+        Ok(TracedTestArgs { pretty })
+    }
+}
+
+pub fn traced_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+    let traced_test_args = match parse_args::<TracedTestArgs>(attr, &input_fn) {
+        Ok(args) => args,
+        Err(tokens) => return tokens,
+    };
+
+    let output = if traced_test_args.pretty {
+        quote!(Pretty)
+    } else {
+        quote!(Capture)
+    };
+
+    // The guard lives until the end of the test body, restoring the
+    // previous subscriber when it is dropped.
+    input_fn.block.stmts.insert(
+        0,
+        parse_quote! {
+            let __telemetry_batteries_subscriber =
+                telemetry_batteries::testing::test_subscriber(
+                    telemetry_batteries::testing::TestOutput::#output,
+                );
+        },
+    );
+
+    let expanded = quote! {
+        #input_fn
+    };
+
+    TokenStream::from(expanded)
+}
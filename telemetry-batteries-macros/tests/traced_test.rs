@@ -0,0 +1,13 @@
+use telemetry_batteries_macros::traced_test;
+
+#[traced_test]
+#[test]
+fn test_sync() {
+    tracing::info!("sync test");
+}
+
+#[traced_test(pretty)]
+#[tokio::test]
+async fn test_async() {
+    tracing::info!("async test");
+}
@@ -0,0 +1,5 @@
+use telemetry_batteries_macros::datadog;
+
+#[tokio::main]
+#[datadog(service_name = "below")]
+async fn main() {}
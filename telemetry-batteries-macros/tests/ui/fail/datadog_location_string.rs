@@ -0,0 +1,5 @@
+use telemetry_batteries_macros::datadog;
+
+#[datadog(service_name = "location", location = "true")]
+#[tokio::main]
+async fn main() {}
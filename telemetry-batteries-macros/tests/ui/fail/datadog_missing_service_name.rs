@@ -0,0 +1,5 @@
+use telemetry_batteries_macros::datadog;
+
+#[datadog(endpoint = "http://localhost:8126")]
+#[tokio::main]
+async fn main() {}
@@ -0,0 +1,4 @@
+use telemetry_batteries_macros::datadog;
+
+#[datadog(service_name = "sync")]
+fn main() {}
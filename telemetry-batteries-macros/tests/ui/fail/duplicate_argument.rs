@@ -0,0 +1,4 @@
+use telemetry_batteries_macros::statsd;
+
+#[statsd(host = "localhost", host = "127.0.0.1")]
+fn main() {}
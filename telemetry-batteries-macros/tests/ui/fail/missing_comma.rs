@@ -0,0 +1,6 @@
+use telemetry_batteries_macros::timed;
+
+#[timed(name = "work" labels("kind" => "example"))]
+fn work() {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use telemetry_batteries_macros::observed;
+
+#[observed]
+const fn answer() -> u32 {
+    42
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use telemetry_batteries_macros::observed;
+
+#[observed]
+fn describe() -> impl std::fmt::Display {
+    "description"
+}
+
+fn main() {}
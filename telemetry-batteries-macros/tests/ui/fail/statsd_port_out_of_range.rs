@@ -0,0 +1,4 @@
+use telemetry_batteries_macros::statsd;
+
+#[statsd(port = 81250)]
+fn main() {}
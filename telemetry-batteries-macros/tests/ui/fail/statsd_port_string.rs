@@ -0,0 +1,4 @@
+use telemetry_batteries_macros::statsd;
+
+#[statsd(port = "8125")]
+fn main() {}
@@ -0,0 +1,4 @@
+use telemetry_batteries_macros::main;
+
+#[main(statsd(hots = "localhost"))]
+fn main() {}
@@ -0,0 +1,41 @@
+use telemetry_batteries_macros::{counted, observed, timed};
+
+pub struct Store {
+    values: Vec<u32>,
+}
+
+impl Store {
+    #[counted]
+    #[inline]
+    pub(crate) fn get(&self, index: usize) -> Result<&u32, String> {
+        self.values.get(index).ok_or_else(|| format!("no value at {index}"))
+    }
+
+    #[observed(name = "store.push")]
+    #[must_use]
+    pub fn push<T: Into<u32>>(&mut self, value: T) -> usize
+    where
+        T: Copy,
+    {
+        self.values.push(value.into());
+        self.values.len()
+    }
+}
+
+#[timed]
+pub extern "C" fn exported(value: u32) -> u32 {
+    value + 1
+}
+
+#[observed]
+async fn load(store: &mut Store) -> Result<usize, std::io::Error> {
+    Ok(store.push(1u32))
+}
+
+fn main() {
+    let mut store = Store { values: Vec::new() };
+    let _ = store.push(1u8);
+    let _ = store.get(0);
+    let _ = exported(1);
+    let _ = load(&mut store);
+}
@@ -0,0 +1,10 @@
+use telemetry_batteries_macros::statsd;
+
+#[statsd(prefix = "sync_main")]
+fn main() {
+    if std::env::args().count() > 100 {
+        return;
+    }
+
+    metrics::counter!("calls").increment(1);
+}
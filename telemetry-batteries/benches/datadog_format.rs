@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::TracerProvider;
+use telemetry_batteries::tracing::layers::datadog::DatadogFormat;
+use tracing::Span;
+use tracing_subscriber::{fmt, layer::SubscriberExt, Layer, Registry};
+
+fn request_span() -> Span {
+    tracing::info_span!("request", path = "/v1/users")
+}
+
+fn format_event(c: &mut Criterion) {
+    let provider = TracerProvider::builder().build();
+    let format_layer = fmt::Layer::new()
+        .json()
+        .event_format(DatadogFormat::new(false))
+        .with_writer(std::io::sink);
+    let otel_layer = tracing_opentelemetry::OpenTelemetryLayer::new(
+        provider.tracer("bench"),
+    );
+    let subscriber =
+        Registry::default().with(format_layer.and_then(otel_layer));
+
+    // Events are also recorded on their span by the otel layer, so each
+    // iteration gets a fresh span, created and dropped outside the
+    // measurement, to keep memory bounded.
+    tracing::subscriber::with_default(subscriber, || {
+        c.bench_function("datadog_format_event", |b| {
+            b.iter_batched(
+                request_span,
+                |span| {
+                    span.in_scope(|| {
+                        tracing::info!(
+                            user_id = 42,
+                            attempt = 3,
+                            "request handled"
+                        )
+                    });
+                    span
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        let error = std::io::Error::other("connection reset");
+        c.bench_function("datadog_format_event_fields", |b| {
+            b.iter_batched(
+                request_span,
+                |span| {
+                    span.in_scope(|| {
+                        tracing::warn!(
+                            user_id = 42,
+                            method = "GET",
+                            cached = false,
+                            latency = 0.25,
+                            headers = ?["accept", "user-agent"],
+                            error = &error as &dyn std::error::Error,
+                            "request failed after {} attempts",
+                            3
+                        )
+                    });
+                    span
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    });
+}
+
+criterion_group!(benches, format_event);
+criterion_main!(benches);
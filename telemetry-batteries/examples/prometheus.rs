@@ -10,7 +10,10 @@ pub fn main() -> eyre::Result<()> {
     };
 
     // Initialize the Prometheus metrics exporter
-    PrometheusBattery::init(Some(prometheus_exporter_config))?;
+    // Metrics are pushed one last time when the handle is dropped in push
+    // gateway mode.
+    let _metrics_handle =
+        PrometheusBattery::init_with_handle(Some(prometheus_exporter_config))?;
 
     metrics::counter!("foo").increment(1);
 
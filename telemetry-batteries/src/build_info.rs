@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+const UNKNOWN: &str = "unknown";
+
+/// Version information of the running binary, reported by [`BuildInfo::emit`]
+/// so dashboards can slice incidents by deployed version.
+///
+/// Use [`build_info!`](crate::build_info!) to fill it in from the calling
+/// crate's build environment, or deserialize it from config.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_sha: Option<String>,
+    pub rustc: Option<String>,
+    pub profile: Option<String>,
+}
+
+impl BuildInfo {
+    /// Sets the `build_info` gauge to 1, labeled with the version fields, and
+    /// logs them as a startup event.
+    ///
+    /// Call this after the metrics and tracing batteries are initialized.
+    pub fn emit(&self) {
+        let git_sha = self.git_sha.as_deref().unwrap_or(UNKNOWN);
+        let rustc = self.rustc.as_deref().unwrap_or(UNKNOWN);
+        let profile = self.profile.as_deref().unwrap_or(UNKNOWN);
+
+        metrics::gauge!(
+            "build_info",
+            "version" => self.version.clone(),
+            "git_sha" => git_sha.to_string(),
+            "rustc" => rustc.to_string(),
+            "profile" => profile.to_string()
+        )
+        .set(1.0);
+
+        tracing::info!(
+            version = self.version,
+            git_sha,
+            rustc,
+            profile,
+            "starting"
+        );
+    }
+}
+
+/// Builds a [`BuildInfo`] for the calling crate.
+///
+/// The version is the crate's `CARGO_PKG_VERSION` and the profile follows
+/// `debug_assertions`. The git sha and rustc version are read at compile time
+/// from the `VERGEN_GIT_SHA` and `VERGEN_RUSTC_SEMVER` variables set by a
+/// [vergen](https://docs.rs/vergen) build script, if there is one.
+///
+/// ```
+/// telemetry_batteries::build_info!().emit();
+/// ```
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::build_info::BuildInfo {
+            version: ::std::env!("CARGO_PKG_VERSION").to_string(),
+            git_sha: ::std::option_env!("VERGEN_GIT_SHA")
+                .map(::std::string::ToString::to_string),
+            rustc: ::std::option_env!("VERGEN_RUSTC_SEMVER")
+                .map(::std::string::ToString::to_string),
+            profile: Some(
+                if cfg!(debug_assertions) {
+                    "debug"
+                } else {
+                    "release"
+                }
+                .to_string(),
+            ),
+        }
+    };
+}
+
+#[cfg(all(test, feature = "metrics-exporters"))]
+mod tests {
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    #[test]
+    fn test_emit() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        metrics::with_local_recorder(&recorder, || {
+            crate::build_info!().emit();
+        });
+
+        let rendered = recorder.handle().render();
+        assert!(rendered
+            .contains(&format!(r#"version="{}""#, env!("CARGO_PKG_VERSION"))));
+    }
+}
@@ -0,0 +1,95 @@
+//! Cache client spans, for Redis, Memcached and the like.
+//!
+//! ```
+//! use telemetry_batteries::cache_span;
+//!
+//! let span = cache_span!("GET", system = "redis", key = "session:d1b2c3");
+//! let hit = true;
+//! span.record("cache.hit", hit);
+//! ```
+
+/// Creates a `cache.<operation>` span of kind client for a cache command,
+/// such as `GET`, on `system`, the `db.system` of the cache.
+///
+/// The `key` is recorded as `cache.key` through [`hash_key`], so that keys
+/// embedding user data don't leave the process. Whether the command hit can
+/// be recorded later as `cache.hit`. Additional span fields can follow.
+///
+/// The `span.type` is the one Datadog expects for the system, see
+/// [`span_type`].
+#[macro_export]
+macro_rules! cache_span {
+    (
+        $operation:expr,
+        system = $system:expr,
+        key = $key:expr
+        $(, $($fields:tt)*)?
+    ) => {
+        $crate::cache_span!(
+            $operation,
+            system = $system,
+            { $crate::semconv::CACHE_KEY } =
+                %$crate::conventions::cache::hash_key(
+                    ::std::convert::AsRef::<str>::as_ref(&$key),
+                ),
+            $($($fields)*)?
+        )
+    };
+    ($operation:expr, system = $system:expr $(, $($fields:tt)*)?) => {{
+        let system = ::std::string::ToString::to_string(&$system);
+        let operation = ::std::string::ToString::to_string(&$operation);
+        let name = ::std::format!("cache.{}", operation.to_lowercase());
+        $crate::reexports::tracing::info_span!(
+            "cache.command",
+            { $crate::semconv::OTEL_NAME } = %name,
+            { $crate::semconv::OTEL_KIND } = "client",
+            { $crate::semconv::SPAN_TYPE } =
+                $crate::conventions::cache::span_type(&system),
+            { $crate::semconv::DB_SYSTEM } = %system,
+            { $crate::semconv::DB_OPERATION_NAME } = %operation,
+            { $crate::semconv::CACHE_HIT } =
+                $crate::reexports::tracing::field::Empty,
+            $($($fields)*)?
+        )
+    }};
+}
+
+/// Replaces the part of `key` after its namespace, up to the first `:`, with
+/// a stable hash of it, e.g. `session:d1b2c3` becomes
+/// `session:fae88d65431866b8`.
+///
+/// Hashes are the same across processes, so that a key can be followed
+/// between spans.
+pub fn hash_key(key: &str) -> String {
+    let (namespace, rest) = match key.split_once(':') {
+        Some((namespace, rest)) => (&key[..=namespace.len()], rest),
+        None => ("", key),
+    };
+
+    // FNV-1a, unlike the std hashers it is specified.
+    let hash = rest.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    format!("{namespace}{hash:016x}")
+}
+
+/// The Datadog `span.type` of the cache commands of `system`.
+pub fn span_type(system: &str) -> &'static str {
+    match system {
+        "redis" => "redis",
+        "memcached" => "memcached",
+        _ => "cache",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_key() {
+        assert_eq!(hash_key("user:1234:profile"), "user:0810f3b9f20df4ae");
+        assert_eq!(hash_key("42"), "07ee7e07b4b19223");
+    }
+}
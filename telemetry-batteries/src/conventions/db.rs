@@ -0,0 +1,186 @@
+//! Database client spans, see
+//! <https://opentelemetry.io/docs/specs/semconv/database/database-spans/>.
+//!
+//! ```
+//! use telemetry_batteries::db_span;
+//!
+//! let user_id = 42;
+//! let statement = format!("SELECT name FROM users WHERE id = {user_id}");
+//! let span = db_span!(
+//!     "SELECT",
+//!     system = "postgresql",
+//!     statement = statement,
+//!     db.namespace = "accounts",
+//! );
+//! ```
+
+/// Statements longer than this are truncated by [`sanitize_statement`].
+pub const MAX_STATEMENT_LEN: usize = 4096;
+
+/// Creates a `db.query` span of kind client for a database operation, such
+/// as `SELECT`, on `system`, the `db.system` of the database.
+///
+/// When a `statement` is given, it goes through [`sanitize_statement`] and
+/// names the span, which Datadog shows as the resource, so that queries
+/// differing only by their literals are grouped. The span is otherwise
+/// named after the operation. Additional span fields can follow.
+///
+/// The `span.type` is `sql`, for Datadog to render it as a query.
+#[macro_export]
+macro_rules! db_span {
+    (
+        $operation:expr,
+        system = $system:expr,
+        statement = $statement:expr
+        $(, $($fields:tt)*)?
+    ) => {{
+        let statement = $crate::conventions::db::sanitize_statement(
+            ::std::convert::AsRef::<str>::as_ref(&$statement),
+        );
+        $crate::reexports::tracing::info_span!(
+            "db.query",
+            { $crate::semconv::OTEL_NAME } = %statement,
+            { $crate::semconv::OTEL_KIND } = "client",
+            { $crate::semconv::SPAN_TYPE } = "sql",
+            { $crate::semconv::DB_SYSTEM } = %$system,
+            { $crate::semconv::DB_OPERATION_NAME } = %$operation,
+            { $crate::semconv::DB_STATEMENT } = %statement,
+            $($($fields)*)?
+        )
+    }};
+    ($operation:expr, system = $system:expr $(, $($fields:tt)*)?) => {
+        $crate::reexports::tracing::info_span!(
+            "db.query",
+            { $crate::semconv::OTEL_NAME } = %$operation,
+            { $crate::semconv::OTEL_KIND } = "client",
+            { $crate::semconv::SPAN_TYPE } = "sql",
+            { $crate::semconv::DB_SYSTEM } = %$system,
+            { $crate::semconv::DB_OPERATION_NAME } = %$operation,
+            $($($fields)*)?
+        )
+    };
+}
+
+/// Replaces the string and numeric literals of a SQL statement with `?`,
+/// collapses whitespace and truncates it to [`MAX_STATEMENT_LEN`] bytes.
+///
+/// Bind parameters such as `$1` and quoted identifiers are kept.
+pub fn sanitize_statement(statement: &str) -> String {
+    let mut sanitized = String::with_capacity(statement.len());
+    let mut chars = statement.trim().chars().peekable();
+    let mut previous = ' ';
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // Quotes within literals are escaped by doubling them.
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+                sanitized.push('?');
+            }
+            '"' => {
+                sanitized.push(c);
+                for c in chars.by_ref() {
+                    sanitized.push(c);
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_ascii_digit() && !is_identifier(previous) => {
+                while chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || *c == '.')
+                    .is_some()
+                {}
+                sanitized.push('?');
+            }
+            c if c.is_whitespace() => {
+                if previous != ' ' {
+                    sanitized.push(' ');
+                }
+            }
+            c => sanitized.push(c),
+        }
+
+        previous = sanitized.chars().next_back().unwrap_or(' ');
+    }
+
+    if sanitized.len() > MAX_STATEMENT_LEN {
+        let mut end = MAX_STATEMENT_LEN;
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+        sanitized.push_str("...");
+    }
+
+    sanitized
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Creates a [`db_span!`](crate::db_span!) for a sqlx query, taking the
+/// system from the database driver and the operation from the first keyword
+/// of the statement.
+///
+/// ```ignore
+/// use telemetry_batteries::conventions::db::query_span;
+/// use tracing::Instrument;
+///
+/// let query = sqlx::query("SELECT name FROM users WHERE id = $1").bind(42);
+/// let span = query_span(&query);
+/// let row = query.fetch_one(&pool).instrument(span).await?;
+/// ```
+#[cfg(feature = "sqlx")]
+pub fn query_span<'q, DB, E>(query: &E) -> tracing::Span
+where
+    DB: sqlx::Database,
+    E: sqlx::Execute<'q, DB>,
+{
+    let statement = query.sql();
+    let operation = statement
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+
+    crate::db_span!(
+        operation,
+        system = db_system(DB::NAME),
+        statement = statement,
+    )
+}
+
+/// Maps the sqlx driver names to their `db.system` value.
+#[cfg(feature = "sqlx")]
+fn db_system(driver: &str) -> String {
+    match driver {
+        "PostgreSQL" => "postgresql".to_string(),
+        driver => driver.to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_statement() {
+        assert_eq!(
+            sanitize_statement(
+                "SELECT * FROM \"users\"\n    WHERE id = 42 AND score > 1.5\n    \
+                 AND name = 'o''brien' AND t1.id = $1"
+            ),
+            "SELECT * FROM \"users\" WHERE id = ? AND score > ? \
+             AND name = ? AND t1.id = $1"
+        );
+
+        let long = format!("SELECT {}", "a, ".repeat(MAX_STATEMENT_LEN));
+        assert_eq!(sanitize_statement(&long).len(), MAX_STATEMENT_LEN + 3);
+    }
+}
@@ -0,0 +1,120 @@
+//! Producer and consumer spans for message queues, see
+//! <https://opentelemetry.io/docs/specs/semconv/messaging/messaging-spans/>.
+//!
+//! The span context travels in the message headers through the
+//! [`Injector`] and [`Extractor`] traits, which `HashMap<String, String>`
+//! implements and which can be implemented for Kafka headers, SQS message
+//! attributes or AMQP properties.
+//!
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use telemetry_batteries::conventions::messaging::{
+//!     consumer_span, inject_context, producer_span,
+//! };
+//!
+//! let span = producer_span("kafka", "orders");
+//! let mut headers = HashMap::new();
+//! inject_context(&span, &mut headers);
+//!
+//! // On the consumer side, with the headers of the received message.
+//! let span = consumer_span("kafka", "orders", &headers);
+//! ```
+
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TraceContextExt;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::semconv;
+
+/// Creates a span of kind producer for publishing a message to
+/// `destination`, on `system` such as `kafka`, `aws_sqs` or `rabbitmq`.
+///
+/// Its context is sent along with the message by [`inject_context`].
+pub fn producer_span(system: &str, destination: &str) -> Span {
+    tracing::info_span!(
+        "messaging.publish",
+        { semconv::OTEL_NAME } = %format_args!("{destination} publish"),
+        { semconv::OTEL_KIND } = "producer",
+        { semconv::SPAN_TYPE } = "queue",
+        { semconv::MESSAGING_SYSTEM } = system,
+        { semconv::MESSAGING_DESTINATION_NAME } = destination,
+        { semconv::MESSAGING_OPERATION } = "publish",
+    )
+}
+
+/// Creates a span of kind consumer for processing a message received from
+/// `destination`, on `system` such as `kafka`, `aws_sqs` or `rabbitmq`.
+///
+/// The span is linked to the span context propagated in the message
+/// `headers`, if any. It stays in its own trace since a message may be
+/// processed long after it was published.
+pub fn consumer_span(
+    system: &str,
+    destination: &str,
+    headers: &dyn Extractor,
+) -> Span {
+    let span = tracing::info_span!(
+        "messaging.process",
+        { semconv::OTEL_NAME } = %format_args!("{destination} process"),
+        { semconv::OTEL_KIND } = "consumer",
+        { semconv::SPAN_TYPE } = "queue",
+        { semconv::MESSAGING_SYSTEM } = system,
+        { semconv::MESSAGING_DESTINATION_NAME } = destination,
+        { semconv::MESSAGING_OPERATION } = "process",
+    );
+
+    let context =
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(headers)
+        });
+    let span_context = context.span().span_context().clone();
+    if span_context.is_valid() {
+        span.add_link(span_context);
+    }
+
+    span
+}
+
+/// Writes the context of `span`, usually a [`producer_span`], to the
+/// `headers` of a message.
+pub fn inject_context(span: &Span, headers: &mut dyn Injector) {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&span.context(), headers);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn test_inject_context() {
+        opentelemetry::global::set_text_map_propagator(
+            TraceContextPropagator::new(),
+        );
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_opentelemetry::OpenTelemetryLayer::new(
+                provider.tracer("test"),
+            ),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = producer_span("kafka", "orders");
+            let mut headers = HashMap::new();
+            inject_context(&span, &mut headers);
+
+            let trace_id = span.context().span().span_context().trace_id();
+            assert!(headers["traceparent"].contains(&trace_id.to_string()));
+        });
+    }
+}
@@ -0,0 +1,7 @@
+//! Spans following the OpenTelemetry semantic conventions, with the
+//! attributes the Datadog agent uses to render them.
+
+pub mod cache;
+pub mod db;
+#[cfg(feature = "datadog")]
+pub mod messaging;
@@ -0,0 +1,110 @@
+//! The environment variables read by the batteries.
+//!
+//! `TELEMETRY_*` variables:
+//!
+//! - `TELEMETRY_ENABLED`: `false` or `0` disables span export at startup.
+//! - `TELEMETRY_EXPORTER_HEADERS`: `key=value` pairs sent as headers with
+//!   every exporter request.
+//! - `TELEMETRY_DATADOG_PROXY`: proxy URL the Datadog agent is reached
+//!   through.
+//! - `TELEMETRY_SPAN_ATTRIBUTES_ALLOW` and `TELEMETRY_SPAN_ATTRIBUTES_DENY`:
+//!   comma separated span attribute keys kept or removed before export.
+//! - `TELEMETRY_SPAN_NAME_RULES`: JSON array of span name rewrite rules.
+//!
+//! Other variables:
+//!
+//! - `RUST_LOG`: the event and span filter.
+//! - `DD_TRACE_AGENT_TIMEOUT`: seconds after which a request to the Datadog
+//!   agent is abandoned.
+//! - `DD_ENTITY_ID`: the pod UID StatsD metrics are tagged with, when origin
+//!   detection is enabled.
+//! - `OTEL_RESOURCE_ATTRIBUTES`: `key=value` pairs attached to spans and
+//!   logs.
+//! - `NO_COLOR` and `CLICOLOR_FORCE`: whether stdout logs are colored.
+//! - `POD_NAME`, `POD_NAMESPACE`, `NODE_NAME`, `HOSTNAME` and
+//!   `ECS_CONTAINER_METADATA_URI_V4`: read by the resource detectors.
+//!
+//! A misspelled `TELEMETRY_*` variable is otherwise silently ignored, call
+//! [`check`] at startup to reject them.
+
+use std::env;
+
+/// Every `TELEMETRY_*` variable read by the batteries.
+pub const TELEMETRY_VARIABLES: &[&str] = &[
+    "TELEMETRY_ENABLED",
+    "TELEMETRY_EXPORTER_HEADERS",
+    "TELEMETRY_DATADOG_PROXY",
+    "TELEMETRY_SPAN_ATTRIBUTES_ALLOW",
+    "TELEMETRY_SPAN_ATTRIBUTES_DENY",
+    "TELEMETRY_SPAN_NAME_RULES",
+];
+
+const PREFIX: &str = "TELEMETRY_";
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown telemetry environment variables: {}", .0.join(", "))]
+pub struct UnknownVariables(pub Vec<String>);
+
+/// Fails if a `TELEMETRY_*` variable is set that the batteries don't read,
+/// e.g. `TELEMETRY_ENABELD`.
+pub fn check() -> Result<(), UnknownVariables> {
+    let unknown = unknown_variables(
+        env::vars_os().filter_map(|(name, _)| name.into_string().ok()),
+    );
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(UnknownVariables(unknown))
+    }
+}
+
+fn unknown_variables(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut unknown = names
+        .filter(|name| {
+            name.starts_with(PREFIX)
+                && !TELEMETRY_VARIABLES.contains(&name.as_str())
+        })
+        .collect::<Vec<_>>();
+    unknown.sort();
+
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_variables() {
+        let names = ["TELEMETRY_ENABLED", "TELEMETRY_PRESSET", "PATH"];
+
+        assert_eq!(
+            unknown_variables(names.into_iter().map(String::from)),
+            vec!["TELEMETRY_PRESSET".to_string()]
+        );
+    }
+
+    #[cfg(feature = "datadog")]
+    #[test]
+    fn test_lists_every_variable() {
+        use crate::exporter::TELEMETRY_EXPORTER_HEADERS_ENV;
+        use crate::tracing::layers::datadog::DATADOG_PROXY_ENV;
+        use crate::tracing::processor::{
+            SPAN_ATTRIBUTES_ALLOW_ENV, SPAN_ATTRIBUTES_DENY_ENV,
+            SPAN_NAME_RULES_ENV,
+        };
+        use crate::tracing::TELEMETRY_ENABLED_ENV;
+
+        for name in [
+            TELEMETRY_ENABLED_ENV,
+            TELEMETRY_EXPORTER_HEADERS_ENV,
+            DATADOG_PROXY_ENV,
+            SPAN_ATTRIBUTES_ALLOW_ENV,
+            SPAN_ATTRIBUTES_DENY_ENV,
+            SPAN_NAME_RULES_ENV,
+        ] {
+            assert!(TELEMETRY_VARIABLES.contains(&name), "{name}");
+        }
+    }
+}
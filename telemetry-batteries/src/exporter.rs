@@ -0,0 +1,367 @@
+//! Metrics about the telemetry exporters' own requests, recorded under
+//! `telemetry.exporter.*` so a slow agent or rejected payloads show up on
+//! dashboards.
+//!
+//! The exporters' clients honor `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`,
+//! like any reqwest client, unless a proxy is configured explicitly. They
+//! also send the headers of [`TELEMETRY_EXPORTER_HEADERS_ENV`].
+//!
+//! Requests also go through a circuit breaker: once the agent failed 5 times
+//! in a row, requests are skipped for 30 seconds, after which a single probe
+//! request decides whether exporting resumes.
+
+use std::env;
+#[cfg(feature = "datadog")]
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+#[cfg(feature = "datadog")]
+use std::time::Instant;
+
+#[cfg(feature = "datadog")]
+use async_trait::async_trait;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+#[cfg(feature = "datadog")]
+use opentelemetry_http::{Bytes, HttpClient, HttpError, Request, Response};
+
+#[cfg(feature = "datadog")]
+use crate::health;
+use crate::resource::parse_key_values;
+
+/// Wraps the HTTP client of an OpenTelemetry exporter, recording every request
+/// it sends.
+#[cfg(feature = "datadog")]
+#[derive(Debug)]
+pub(crate) struct InstrumentedHttpClient<C> {
+    exporter: &'static str,
+    inner: C,
+    breaker: CircuitBreaker,
+}
+
+#[cfg(feature = "datadog")]
+impl<C> InstrumentedHttpClient<C> {
+    pub(crate) fn new(exporter: &'static str, inner: C) -> Self {
+        Self {
+            exporter,
+            inner,
+            breaker: CircuitBreaker::default(),
+        }
+    }
+}
+
+#[cfg(feature = "datadog")]
+#[async_trait]
+impl<C: HttpClient> HttpClient for InstrumentedHttpClient<C> {
+    async fn send(
+        &self,
+        request: Request<Vec<u8>>,
+    ) -> Result<Response<Bytes>, HttpError> {
+        let size = request.body().len();
+        let start = Instant::now();
+
+        if !self.breaker.allow(start) {
+            metrics::counter!(
+                "telemetry.exporter.requests.skipped",
+                "exporter" => self.exporter
+            )
+            .increment(1);
+
+            // Answered locally with a success, the exporter turns error
+            // statuses into export errors and would report every skipped
+            // batch, the skipped counter is the signal instead.
+            let mut response = Response::new(Bytes::new());
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            return Ok(response);
+        }
+
+        let result = self.inner.send(request).await;
+
+        // The reqwest client turns error statuses into errors.
+        let status = match &result {
+            Ok(response) => Some(response.status()),
+            Err(e) => e
+                .downcast_ref::<reqwest::Error>()
+                .and_then(reqwest::Error::status),
+        };
+        record_request(self.exporter, status, start.elapsed(), size);
+
+        // Rejected payloads mean the agent is reachable.
+        let failed = status.is_none_or(|status| status.is_server_error());
+        match self.breaker.record(failed, Instant::now()) {
+            Some(Transition::Opened) => {
+                tracing::warn!(
+                    exporter = self.exporter,
+                    failures = FAILURE_THRESHOLD,
+                    cooldown = ?COOLDOWN,
+                    "Exporter is failing, pausing exports"
+                );
+            }
+            Some(Transition::Closed { skipped }) => {
+                tracing::info!(
+                    exporter = self.exporter,
+                    skipped,
+                    "Exporter recovered, resuming exports"
+                );
+            }
+            None => {}
+        }
+
+        let circuit_open = self.breaker.is_open();
+        metrics::gauge!(
+            "telemetry.exporter.circuit_open",
+            "exporter" => self.exporter
+        )
+        .set(if circuit_open { 1.0 } else { 0.0 });
+
+        let error = match &result {
+            Ok(response) if !response.status().is_success() => {
+                Some(format!("agent responded with {}", response.status()))
+            }
+            Ok(_) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        health::record_export(self.exporter, error, circuit_open);
+
+        result
+    }
+}
+
+/// Consecutive failed requests after which requests are skipped.
+#[cfg(feature = "datadog")]
+const FAILURE_THRESHOLD: u32 = 5;
+
+/// How long requests are skipped before a probe request is sent.
+#[cfg(feature = "datadog")]
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+#[cfg(feature = "datadog")]
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+}
+
+#[cfg(feature = "datadog")]
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    // Set while the circuit is open.
+    open_until: Option<Instant>,
+    skipped: u64,
+}
+
+#[cfg(feature = "datadog")]
+#[derive(Debug, PartialEq, Eq)]
+enum Transition {
+    Opened,
+    Closed { skipped: u64 },
+}
+
+#[cfg(feature = "datadog")]
+impl CircuitBreaker {
+    /// Returns whether a request may be sent. Once the cooldown elapsed, a
+    /// single probe is let through and the cooldown restarts, so concurrent
+    /// requests keep being skipped until the probe completes.
+    fn allow(&self, now: Instant) -> bool {
+        let mut state =
+            self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match state.open_until {
+            None => true,
+            Some(open_until) if now >= open_until => {
+                state.open_until = Some(now + COOLDOWN);
+                true
+            }
+            Some(_) => {
+                state.skipped += 1;
+                false
+            }
+        }
+    }
+
+    /// Records the outcome of a request sent after [`Self::allow`].
+    fn record(&self, failed: bool, now: Instant) -> Option<Transition> {
+        let mut state =
+            self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if !failed {
+            state.consecutive_failures = 0;
+            state.open_until.take()?;
+            let skipped = std::mem::take(&mut state.skipped);
+            return Some(Transition::Closed { skipped });
+        }
+
+        state.consecutive_failures =
+            state.consecutive_failures.saturating_add(1);
+        let was_open = state.open_until.is_some();
+        if was_open || state.consecutive_failures >= FAILURE_THRESHOLD {
+            state.open_until = Some(now + COOLDOWN);
+        }
+
+        (!was_open && state.open_until.is_some()).then_some(Transition::Opened)
+    }
+
+    fn is_open(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .open_until
+            .is_some()
+    }
+}
+
+/// Headers sent with every exporter request, e.g. for an authenticating
+/// proxy, as comma separated `key=value` pairs with percent-encoded values:
+/// `authorization=Bearer%20token,x-team=platform`.
+pub const TELEMETRY_EXPORTER_HEADERS_ENV: &str = "TELEMETRY_EXPORTER_HEADERS";
+
+/// The headers of [`TELEMETRY_EXPORTER_HEADERS_ENV`], overridden by
+/// `overrides`. Invalid names and values are skipped.
+pub(crate) fn exporter_headers(
+    overrides: impl IntoIterator<Item = (String, String)>,
+) -> HeaderMap {
+    let from_env = env::var(TELEMETRY_EXPORTER_HEADERS_ENV).unwrap_or_default();
+
+    parse_headers(&from_env, overrides)
+}
+
+/// The headers of `value`, formatted like [`TELEMETRY_EXPORTER_HEADERS_ENV`],
+/// overridden by `overrides`.
+fn parse_headers(
+    value: &str,
+    overrides: impl IntoIterator<Item = (String, String)>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in parse_key_values(value).chain(overrides) {
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::try_from(name), HeaderValue::try_from(value))
+        {
+            headers.insert(name, value);
+        }
+    }
+
+    headers
+}
+
+/// Proxy for the requests to `url`, overriding `HTTPS_PROXY` and
+/// `HTTP_PROXY`. Hosts listed in `NO_PROXY` still bypass it.
+pub(crate) fn proxy(url: &str) -> reqwest::Result<reqwest::Proxy> {
+    Ok(reqwest::Proxy::all(url)?.no_proxy(reqwest::NoProxy::from_env()))
+}
+
+/// Records a request sent by `exporter`, `status` is `None` if no response
+/// was received.
+pub(crate) fn record_request(
+    exporter: &'static str,
+    status: Option<StatusCode>,
+    duration: Duration,
+    size: usize,
+) {
+    let status = status
+        .map_or_else(|| "error".to_string(), |status| status.as_str().into());
+
+    metrics::counter!(
+        "telemetry.exporter.requests",
+        "exporter" => exporter,
+        "status" => status
+    )
+    .increment(1);
+    metrics::histogram!(
+        "telemetry.exporter.request.duration",
+        "exporter" => exporter
+    )
+    .record(duration.as_secs_f64());
+    metrics::histogram!(
+        "telemetry.exporter.request.size",
+        "exporter" => exporter
+    )
+    .record(size as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exporter_headers() {
+        let headers = parse_headers(
+            "authorization=Bearer%20secret,x-team=platform,bad name=1",
+            [("x-team".to_string(), "observability".to_string())],
+        );
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["authorization"], "Bearer secret");
+        assert_eq!(headers["x-team"], "observability");
+    }
+
+    #[cfg(feature = "datadog")]
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::default();
+        let now = Instant::now();
+
+        for _ in 1..FAILURE_THRESHOLD {
+            assert!(breaker.allow(now));
+            assert_eq!(breaker.record(true, now), None);
+        }
+        assert!(breaker.allow(now));
+        assert_eq!(breaker.record(true, now), Some(Transition::Opened));
+
+        assert!(!breaker.allow(now + COOLDOWN / 2));
+        assert!(!breaker.allow(now + COOLDOWN / 2));
+
+        // The probe fails, the circuit stays open for another cooldown.
+        let probe = now + COOLDOWN;
+        assert!(breaker.allow(probe));
+        assert!(!breaker.allow(probe));
+        assert_eq!(breaker.record(true, probe), None);
+        assert!(!breaker.allow(probe + COOLDOWN / 2));
+
+        let probe = probe + COOLDOWN;
+        assert!(breaker.allow(probe));
+        assert_eq!(
+            breaker.record(false, probe),
+            Some(Transition::Closed { skipped: 4 })
+        );
+        assert!(breaker.allow(probe));
+        assert!(!breaker.is_open());
+    }
+
+    /// Panics if a request gets through the open circuit.
+    #[cfg(feature = "datadog")]
+    #[derive(Debug)]
+    struct Unreachable;
+
+    #[cfg(feature = "datadog")]
+    #[async_trait]
+    impl HttpClient for Unreachable {
+        async fn send(
+            &self,
+            _request: Request<Vec<u8>>,
+        ) -> Result<Response<Bytes>, HttpError> {
+            panic!("request sent while the circuit is open");
+        }
+    }
+
+    #[cfg(feature = "datadog")]
+    #[test]
+    fn test_open_circuit_exports_ok() {
+        use opentelemetry_sdk::export::trace::SpanExporter;
+
+        // Opened directly, failed requests would mark the exporter unhealthy
+        // for the other tests.
+        let client = InstrumentedHttpClient::new("test", Unreachable);
+        let now = Instant::now();
+        for _ in 0..FAILURE_THRESHOLD {
+            client.breaker.record(true, now);
+        }
+
+        let mut exporter = opentelemetry_datadog::new_pipeline()
+            .with_http_client(client)
+            .build_exporter()
+            .unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        assert!(runtime.block_on(exporter.export(vec![])).is_ok());
+    }
+}
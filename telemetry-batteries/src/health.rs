@@ -0,0 +1,87 @@
+//! Health of the telemetry exporters, for readiness probes and diagnostics
+//! endpoints.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+static EXPORTERS: Mutex<BTreeMap<&'static str, ExporterState>> =
+    Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Default)]
+struct ExporterState {
+    circuit_open: bool,
+    last_error: Option<ExportFailure>,
+}
+
+/// The last request of an exporter that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportFailure {
+    /// Name of the exporter, e.g. `datadog`.
+    pub exporter: &'static str,
+    pub message: String,
+    pub at: SystemTime,
+}
+
+/// Returns `false` while an exporter is pausing exports because its agent
+/// kept failing. Occasional failed requests don't make the exporters
+/// unhealthy.
+pub fn is_healthy() -> bool {
+    exporters().values().all(|state| !state.circuit_open)
+}
+
+/// Returns the most recent failed export request, if any. It is kept after
+/// the exporter recovered, compare [`ExportFailure::at`] to judge whether it
+/// is still relevant.
+pub fn last_export_error() -> Option<ExportFailure> {
+    exporters()
+        .values()
+        .filter_map(|state| state.last_error.clone())
+        .max_by_key(|failure| failure.at)
+}
+
+/// Records the outcome of a request sent by `exporter`.
+#[cfg(feature = "datadog")]
+pub(crate) fn record_export(
+    exporter: &'static str,
+    error: Option<String>,
+    circuit_open: bool,
+) {
+    let mut exporters = exporters();
+    let state = exporters.entry(exporter).or_default();
+
+    state.circuit_open = circuit_open;
+    if let Some(message) = error {
+        state.last_error = Some(ExportFailure {
+            exporter,
+            message,
+            at: SystemTime::now(),
+        });
+    }
+}
+
+fn exporters() -> MutexGuard<'static, BTreeMap<&'static str, ExporterState>> {
+    EXPORTERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(all(test, feature = "datadog"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        record_export("test", Some("connection refused".to_string()), true);
+        assert!(!is_healthy());
+        assert_eq!(
+            last_export_error().map(|failure| failure.message),
+            Some("connection refused".to_string())
+        );
+
+        record_export("test", None, false);
+        assert!(is_healthy());
+        assert_eq!(
+            last_export_error().map(|failure| failure.exporter),
+            Some("test")
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Periodic liveness signal, so a service or exporter that dies silently is
+//! noticed by the heartbeat's absence.
+
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::{io, thread};
+
+pub struct HeartbeatBattery;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    // How often `service.heartbeat` is incremented and the liveness event is
+    // logged, must not be zero.
+    pub interval: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl HeartbeatBattery {
+    /// Starts a background thread incrementing the `service.heartbeat`
+    /// counter and logging the time since the heartbeat started every
+    /// `config.interval`.
+    ///
+    /// # Errors
+    /// Returns an error if `config.interval` is zero or the thread can't be
+    /// spawned.
+    pub fn init(
+        config: HeartbeatConfig,
+    ) -> io::Result<HeartbeatShutdownHandle> {
+        if config.interval.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "heartbeat interval must not be zero",
+            ));
+        }
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let started_at = Instant::now();
+
+        thread::Builder::new()
+            .name("telemetry-batteries-heartbeat".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(config.interval)
+                {
+                    // Measured from `init`, which may run well after the
+                    // process started.
+                    let running = started_at.elapsed();
+
+                    metrics::counter!("service.heartbeat").increment(1);
+                    metrics::gauge!("service.heartbeat.running_time")
+                        .set(running.as_secs_f64());
+
+                    tracing::info!(
+                        running_secs = running.as_secs(),
+                        "heartbeat"
+                    );
+                }
+            })?;
+
+        Ok(HeartbeatShutdownHandle { _stop: stop })
+    }
+}
+
+/// `HeartbeatShutdownHandle` stops the heartbeat when dropped.
+#[must_use]
+pub struct HeartbeatShutdownHandle {
+    _stop: mpsc::Sender<()>,
+}
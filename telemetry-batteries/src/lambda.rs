@@ -0,0 +1,71 @@
+//! Support for AWS Lambda, where the process is frozen between invocations
+//! and the batch exporter's timer never gets to fire.
+//!
+//! Handlers are wrapped with [`instrument_invocation`], which flushes spans
+//! and StatsD metrics before the response is returned to the runtime:
+//!
+//! ```no_run
+//! use telemetry_batteries::lambda::instrument_invocation;
+//!
+//! # async fn handle(event: String) -> String { event }
+//! # async fn run(request_id: &str, event: String) {
+//! let response =
+//!     instrument_invocation(request_id, handle(event)).await;
+//! # }
+//! ```
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tracing::Instrument;
+
+use crate::metrics::statsd::StatsdBattery;
+use crate::semconv;
+use crate::tracing::flush_tracer_provider;
+
+/// How long [`instrument_invocation`] waits for spans and metrics to be
+/// sent.
+pub const DEFAULT_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+static COLD_START: AtomicBool = AtomicBool::new(true);
+
+/// Runs `handler` in a `lambda.invocation` span and flushes spans and
+/// metrics once it completes, waiting at most [`DEFAULT_FLUSH_TIMEOUT`].
+///
+/// The span has the `faas.invocation_id` and `faas.coldstart` attributes,
+/// the latter set on the first invocation of the process only.
+pub async fn instrument_invocation<F: Future>(
+    request_id: &str,
+    handler: F,
+) -> F::Output {
+    let cold_start = COLD_START.swap(false, Ordering::Relaxed);
+    let span = tracing::info_span!(
+        "lambda.invocation",
+        { semconv::FAAS_INVOCATION_ID } = request_id,
+        { semconv::FAAS_COLDSTART } = cold_start,
+    );
+
+    let output = handler.instrument(span).await;
+    flush(DEFAULT_FLUSH_TIMEOUT).await;
+
+    output
+}
+
+/// Flushes spans and StatsD metrics, waiting at most `timeout` for each.
+/// Returns `false` if spans could not be flushed in time.
+pub async fn flush(timeout: Duration) -> bool {
+    // Both flushes block, the batch processor keeps running meanwhile.
+    let flushed = tokio::task::spawn_blocking(move || {
+        let flushed = flush_tracer_provider(timeout);
+
+        if let Err(error) = StatsdBattery::flush(timeout) {
+            tracing::warn!(%error, "Failed to flush metrics");
+        }
+
+        flushed
+    })
+    .await;
+
+    flushed.unwrap_or(false)
+}
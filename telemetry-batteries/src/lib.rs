@@ -1,6 +1,29 @@
+pub mod build_info;
+pub mod conventions;
+pub mod environment;
+#[cfg(any(feature = "datadog", feature = "metrics-exporters"))]
+pub mod exporter;
+pub mod health;
+pub mod heartbeat;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+#[cfg(any(feature = "metrics-exporters", feature = "grpc"))]
 pub mod metrics;
+pub mod resource;
+pub mod semconv;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod tracing;
 
+// The exporters need tokio's multi-threaded runtime and native TLS.
+#[cfg(all(
+    target_arch = "wasm32",
+    any(feature = "datadog", feature = "metrics-exporters")
+))]
+compile_error!(
+    "the exporters are not available on wasm32, disable the default features"
+);
+
 /// Reexports of crates that appear in the public API.
 ///
 /// Using these directly instead of adding them yourself to Cargo.toml will help avoid
@@ -8,5 +31,9 @@ pub mod tracing;
 /// crate versions.
 pub mod reexports {
     pub use ::metrics;
+    #[cfg(feature = "datadog")]
     pub use ::opentelemetry;
+    #[cfg(feature = "datadog")]
+    pub use ::opentelemetry_sdk;
+    pub use ::tracing;
 }
@@ -0,0 +1,347 @@
+//! Tower layer recording the RPC metrics of gRPC calls, on the server or
+//! client side, see
+//! <https://opentelemetry.io/docs/specs/semconv/rpc/rpc-metrics/>.
+//!
+//! With tonic:
+//!
+//! ```ignore
+//! use telemetry_batteries::metrics::grpc::GrpcMetricsLayer;
+//!
+//! Server::builder()
+//!     .layer(GrpcMetricsLayer::server())
+//!     .add_service(UsersServer::new(users))
+//!     .serve(address)
+//!     .await?;
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+use http_body::{Body, Frame, SizeHint};
+use metrics::Label;
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::semconv;
+
+const STATUS_HEADER: &str = "grpc-status";
+
+// Recorded when the status never reached the layer.
+const CANCELLED: &str = "1";
+const UNKNOWN: &str = "2";
+
+/// Records, for every call going through the wrapped service:
+///
+/// - `rpc.<side>.duration`: a histogram of the call durations in seconds,
+///   until the last response message,
+/// - `rpc.<side>.requests`: a counter of the calls,
+/// - `rpc.<side>.errors`: a counter of the calls that did not end with the
+///   `OK` status,
+///
+/// labeled with `rpc.system`, `rpc.service`, `rpc.method` and
+/// `rpc.grpc.status_code`.
+///
+/// The service and method come from the request path, on servers it can be
+/// anything sent by clients, so consider capping their cardinality with a
+/// [`NamingPolicy`](crate::metrics::naming::NamingPolicy).
+#[derive(Debug, Clone, Copy)]
+pub struct GrpcMetricsLayer {
+    names: &'static MetricNames,
+}
+
+#[derive(Debug)]
+struct MetricNames {
+    duration: &'static str,
+    requests: &'static str,
+    errors: &'static str,
+}
+
+impl GrpcMetricsLayer {
+    /// Records `rpc.server.*` metrics for the calls handled by a server.
+    pub fn server() -> Self {
+        Self {
+            names: &MetricNames {
+                duration: "rpc.server.duration",
+                requests: "rpc.server.requests",
+                errors: "rpc.server.errors",
+            },
+        }
+    }
+
+    /// Records `rpc.client.*` metrics for the calls made by a client.
+    pub fn client() -> Self {
+        Self {
+            names: &MetricNames {
+                duration: "rpc.client.duration",
+                requests: "rpc.client.requests",
+                errors: "rpc.client.errors",
+            },
+        }
+    }
+}
+
+impl<S> Layer<S> for GrpcMetricsLayer {
+    type Service = GrpcMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcMetrics {
+            inner,
+            names: self.names,
+        }
+    }
+}
+
+/// Service built by [`GrpcMetricsLayer`].
+#[derive(Debug, Clone)]
+pub struct GrpcMetrics<S> {
+    inner: S,
+    names: &'static MetricNames,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for GrpcMetrics<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = http::Response<MetricsBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let call = Call::new(self.names, request.uri().path());
+
+        ResponseFuture {
+            inner: self.inner.call(request),
+            call: Some(call),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future of [`GrpcMetrics`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        call: Option<Call>,
+    }
+}
+
+impl<F, ResBody, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<http::Response<ResBody>, E>>,
+{
+    type Output = Result<http::Response<MetricsBody<ResBody>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        let call = this.call.take().expect("polled after completion");
+
+        let result = match result {
+            Ok(response) => {
+                // Trailers-only responses, usually errors, carry the status
+                // in their headers.
+                let call = match grpc_status(response.headers()) {
+                    Some(status) => {
+                        call.finish(status);
+                        None
+                    }
+                    None => Some(call),
+                };
+
+                Ok(response.map(|inner| MetricsBody { inner, call }))
+            }
+            Err(error) => {
+                call.finish(UNKNOWN);
+                Err(error)
+            }
+        };
+
+        Poll::Ready(result)
+    }
+}
+
+pin_project! {
+    /// Response body of [`GrpcMetrics`], recording the call once the status
+    /// is read from the trailers.
+    pub struct MetricsBody<B> {
+        #[pin]
+        inner: B,
+        call: Option<Call>,
+    }
+}
+
+impl<B: Body> Body for MetricsBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.inner.poll_frame(cx));
+
+        let status = match &frame {
+            Some(Ok(frame)) => frame
+                .trailers_ref()
+                .map(|trailers| grpc_status(trailers).unwrap_or(UNKNOWN)),
+            Some(Err(_)) | None => Some(UNKNOWN),
+        };
+        if let Some(status) = status {
+            if let Some(call) = this.call.take() {
+                call.finish(status);
+            }
+        }
+
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Records the metrics of a call when dropped, as cancelled unless
+/// [`Call::finish`] was called with its status.
+struct Call {
+    names: &'static MetricNames,
+    service: String,
+    method: String,
+    start: Instant,
+    status: Option<String>,
+}
+
+impl Call {
+    fn new(names: &'static MetricNames, path: &str) -> Self {
+        // Paths are `/<package>.<service>/<method>`.
+        let (service, method) = path
+            .trim_start_matches('/')
+            .split_once('/')
+            .unwrap_or((path, ""));
+
+        Self {
+            names,
+            service: service.to_string(),
+            method: method.to_string(),
+            start: Instant::now(),
+            status: None,
+        }
+    }
+
+    fn finish(mut self, status: &str) {
+        self.status = Some(status.to_string());
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        let status = self.status.take().unwrap_or(CANCELLED.to_string());
+        let is_error = status != "0";
+        let labels = vec![
+            Label::new(semconv::RPC_SYSTEM, "grpc"),
+            Label::new(semconv::RPC_SERVICE, std::mem::take(&mut self.service)),
+            Label::new(semconv::RPC_METHOD, std::mem::take(&mut self.method)),
+            Label::new(semconv::RPC_GRPC_STATUS_CODE, status),
+        ];
+
+        metrics::histogram!(self.names.duration, labels.clone())
+            .record(self.start.elapsed());
+        metrics::counter!(self.names.requests, labels.clone()).increment(1);
+        if is_error {
+            metrics::counter!(self.names.errors, labels).increment(1);
+        }
+    }
+}
+
+fn grpc_status(headers: &http::HeaderMap) -> Option<&str> {
+    headers.get(STATUS_HEADER)?.to_str().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use std::convert::Infallible;
+    use std::future::{poll_fn, ready, Ready};
+
+    /// Responds with an empty body ending with `grpc-status: 5`.
+    struct NotFound;
+
+    impl Service<http::Request<()>> for NotFound {
+        type Response = http::Response<Trailers>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _request: http::Request<()>) -> Self::Future {
+            let mut trailers = http::HeaderMap::new();
+            trailers.insert(STATUS_HEADER, "5".parse().unwrap());
+            ready(Ok(http::Response::new(Trailers(Some(trailers)))))
+        }
+    }
+
+    struct Trailers(Option<http::HeaderMap>);
+
+    impl Body for Trailers {
+        type Data = &'static [u8];
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            Poll::Ready(
+                self.0.take().map(|trailers| Ok(Frame::trailers(trailers))),
+            )
+        }
+    }
+
+    #[test]
+    fn test_status_from_trailers() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let mut service = GrpcMetricsLayer::server().layer(NotFound);
+                let request = http::Request::builder()
+                    .uri("/users.v1.Users/Get")
+                    .body(())
+                    .unwrap();
+                let mut body = service.call(request).await.unwrap().into_body();
+                while poll_fn(|cx| Pin::new(&mut body).poll_frame(cx))
+                    .await
+                    .is_some()
+                {}
+            });
+        });
+
+        let rendered = recorder.handle().render();
+        assert!(rendered.contains(
+            r#"rpc_server_errors{rpc_system="grpc",rpc_service="users.v1.Users",rpc_method="Get",rpc_grpc_status_code="5"} 1"#
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+//! Declarative metric descriptions, registered with the installed recorder at
+//! startup so every service exports the same units and help text.
+
+use metrics::{KeyName, SharedString, Unit};
+
+use super::prometheus::MetricKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricDescription {
+    pub kind: MetricKind,
+    pub name: &'static str,
+    pub unit: Option<Unit>,
+    pub description: &'static str,
+}
+
+impl MetricDescription {
+    pub const fn counter(
+        name: &'static str,
+        description: &'static str,
+    ) -> Self {
+        Self::new(MetricKind::Counter, name, description)
+    }
+
+    pub const fn gauge(name: &'static str, description: &'static str) -> Self {
+        Self::new(MetricKind::Gauge, name, description)
+    }
+
+    pub const fn histogram(
+        name: &'static str,
+        description: &'static str,
+    ) -> Self {
+        Self::new(MetricKind::Histogram, name, description)
+    }
+
+    pub const fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    const fn new(
+        kind: MetricKind,
+        name: &'static str,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            kind,
+            name,
+            unit: None,
+            description,
+        }
+    }
+}
+
+/// Describes every metric in `descriptions` to the current recorder.
+///
+/// Must be called after the metrics battery is initialized, descriptions sent
+/// before a recorder is installed are dropped.
+///
+/// ```
+/// use telemetry_batteries::metrics::metadata::{self, MetricDescription};
+/// use telemetry_batteries::reexports::metrics::Unit;
+///
+/// const METRICS: &[MetricDescription] = &[
+///     MetricDescription::counter("requests_total", "Requests served"),
+///     MetricDescription::histogram("request_duration", "Request latency")
+///         .with_unit(Unit::Seconds),
+/// ];
+///
+/// metadata::describe(METRICS);
+/// ```
+pub fn describe(descriptions: &[MetricDescription]) {
+    metrics::with_recorder(|recorder| {
+        for metric in descriptions {
+            let name = KeyName::from_const_str(metric.name);
+            let description = SharedString::const_str(metric.description);
+
+            match metric.kind {
+                MetricKind::Counter => {
+                    recorder.describe_counter(name, metric.unit, description)
+                }
+                MetricKind::Gauge => {
+                    recorder.describe_gauge(name, metric.unit, description)
+                }
+                MetricKind::Histogram => {
+                    recorder.describe_histogram(name, metric.unit, description)
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    #[test]
+    fn test_describe_renders_help() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        metrics::with_local_recorder(&recorder, || {
+            describe(&[
+                MetricDescription::counter("requests_total", "Requests served"),
+                MetricDescription::gauge("queue_depth", "Queued jobs")
+                    .with_unit(Unit::Count),
+            ]);
+
+            metrics::counter!("requests_total").increment(1);
+            metrics::gauge!("queue_depth").set(3.0);
+        });
+
+        let rendered = recorder.handle().render();
+        assert!(rendered.contains("# HELP requests_total Requests served"));
+        assert!(rendered.contains("# TYPE requests_total counter"));
+        assert!(rendered.contains("# HELP queue_depth"));
+    }
+}
@@ -1,2 +1,196 @@
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "metrics-exporters")]
+pub mod metadata;
+#[cfg(feature = "metrics-exporters")]
+pub mod naming;
+#[cfg(feature = "metrics-exporters")]
 pub mod prometheus;
+#[cfg(feature = "metrics-exporters")]
 pub mod statsd;
+#[cfg(feature = "system-metrics")]
+pub mod system;
+#[cfg(feature = "metrics-exporters")]
+pub mod timer;
+
+#[cfg(feature = "metrics-exporters")]
+use metrics::Recorder;
+#[cfg(feature = "metrics-exporters")]
+use metrics_exporter_statsd::StatsdError;
+#[cfg(feature = "metrics-exporters")]
+use metrics_util::layers::{FanoutBuilder, Layer};
+
+#[cfg(feature = "metrics-exporters")]
+use self::naming::NamingPolicy;
+#[cfg(feature = "metrics-exporters")]
+use self::prometheus::{
+    PrometheusBattery, PrometheusConfig, PrometheusError,
+    PrometheusShutdownHandle,
+};
+#[cfg(feature = "metrics-exporters")]
+use self::statsd::{StatsdBattery, StatsdConfig};
+#[cfg(feature = "system-metrics")]
+use self::system::{
+    SystemMetricsBattery, SystemMetricsConfig, SystemMetricsShutdownHandle,
+};
+
+#[cfg(feature = "metrics-exporters")]
+pub struct MetricsBattery;
+
+#[cfg(feature = "metrics-exporters")]
+#[derive(Debug, Clone)]
+pub enum MetricsBackend {
+    Prometheus(PrometheusConfig),
+    Statsd(StatsdConfig),
+
+    // Records every metric in both backends, e.g. to double-emit while
+    // migrating dashboards from one to the other.
+    Multi {
+        prometheus: PrometheusConfig,
+        statsd: StatsdConfig,
+    },
+}
+
+/// Everything [`MetricsBattery::init_with_config`] sets up.
+#[cfg(feature = "metrics-exporters")]
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    pub backend: MetricsBackend,
+
+    // Rewrites every metric before it reaches the backend.
+    pub naming_policy: Option<NamingPolicy>,
+
+    // Collects host-wide metrics, see `SystemMetricsBattery`.
+    #[cfg(feature = "system-metrics")]
+    pub system: Option<SystemMetricsConfig>,
+}
+
+#[cfg(feature = "metrics-exporters")]
+impl From<MetricsBackend> for MetricsConfig {
+    fn from(backend: MetricsBackend) -> Self {
+        Self {
+            backend,
+            naming_policy: None,
+            #[cfg(feature = "system-metrics")]
+            system: None,
+        }
+    }
+}
+
+#[cfg(feature = "metrics-exporters")]
+#[derive(Debug, thiserror::Error)]
+pub enum MetricsError {
+    #[error(transparent)]
+    Prometheus(#[from] PrometheusError),
+
+    #[error(transparent)]
+    Statsd(#[from] StatsdError),
+
+    #[error("failed to install recorder, a global recorder is already set")]
+    RecorderAlreadySet,
+
+    #[cfg(feature = "system-metrics")]
+    #[error("failed to start the system metrics collector: {0}")]
+    SystemMetrics(std::io::Error),
+}
+
+#[cfg(feature = "metrics-exporters")]
+impl MetricsBattery {
+    /// Installs the recorder for `backend` globally.
+    ///
+    /// The returned handle holds the Prometheus shutdown handle, if any.
+    pub fn init(
+        backend: MetricsBackend,
+    ) -> Result<MetricsShutdownHandle, MetricsError> {
+        Self::install(backend, None)
+    }
+
+    /// Like [`MetricsBattery::init`], but every metric is rewritten to follow
+    /// `policy` before it reaches the backend.
+    pub fn init_with_naming_policy(
+        backend: MetricsBackend,
+        policy: NamingPolicy,
+    ) -> Result<MetricsShutdownHandle, MetricsError> {
+        Self::install(backend, Some(policy))
+    }
+
+    /// Installs the recorder for `config.backend` globally, and starts the
+    /// system metrics collector if configured.
+    pub fn init_with_config(
+        config: MetricsConfig,
+    ) -> Result<MetricsShutdownHandle, MetricsError> {
+        #[allow(unused_mut)]
+        let mut handle = Self::install(config.backend, config.naming_policy)?;
+
+        #[cfg(feature = "system-metrics")]
+        if let Some(system) = config.system {
+            handle.system = Some(
+                SystemMetricsBattery::init(system)
+                    .map_err(MetricsError::SystemMetrics)?,
+            );
+        }
+
+        Ok(handle)
+    }
+
+    fn install(
+        backend: MetricsBackend,
+        policy: Option<NamingPolicy>,
+    ) -> Result<MetricsShutdownHandle, MetricsError> {
+        let (recorder, prometheus): (Box<dyn Recorder + Sync>, _) =
+            match backend {
+                MetricsBackend::Prometheus(config) => {
+                    let (recorder, prometheus) =
+                        PrometheusBattery::build(config)?;
+                    (Box::new(recorder), Some(prometheus))
+                }
+                MetricsBackend::Statsd(config) => {
+                    (Box::new(StatsdBattery::build(&config)?), None)
+                }
+                MetricsBackend::Multi { prometheus, statsd } => {
+                    let (prometheus_recorder, prometheus) =
+                        PrometheusBattery::build(prometheus)?;
+                    let statsd_recorder = StatsdBattery::build(&statsd)?;
+
+                    let fanout = FanoutBuilder::default()
+                        .add_recorder(prometheus_recorder)
+                        .add_recorder(statsd_recorder)
+                        .build();
+
+                    (Box::new(fanout), Some(prometheus))
+                }
+            };
+
+        let recorder: Box<dyn Recorder + Sync> = match policy {
+            Some(policy) => Box::new(policy.layer(recorder)),
+            None => recorder,
+        };
+
+        metrics::set_global_recorder(recorder)
+            .map_err(|_| MetricsError::RecorderAlreadySet)?;
+
+        Ok(MetricsShutdownHandle {
+            prometheus,
+            #[cfg(feature = "system-metrics")]
+            system: None,
+        })
+    }
+}
+
+/// `MetricsShutdownHandle` keeps the installed exporters alive, see
+/// [`PrometheusShutdownHandle`] for what happens on drop. The system metrics
+/// collector, if any, stops when it is dropped.
+#[cfg(feature = "metrics-exporters")]
+#[must_use]
+pub struct MetricsShutdownHandle {
+    prometheus: Option<PrometheusShutdownHandle>,
+    #[cfg(feature = "system-metrics")]
+    system: Option<SystemMetricsShutdownHandle>,
+}
+
+#[cfg(feature = "metrics-exporters")]
+impl MetricsShutdownHandle {
+    pub fn prometheus(&self) -> Option<&PrometheusShutdownHandle> {
+        self.prometheus.as_ref()
+    }
+}
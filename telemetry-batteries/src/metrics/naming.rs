@@ -0,0 +1,280 @@
+//! Recorder layer enforcing a naming policy on every metric before it reaches
+//! the backend, see [`NamingPolicy`].
+
+use metrics::{
+    Counter, Gauge, Histogram, Key, KeyName, Label, Metadata, Recorder,
+    SharedString, Unit,
+};
+use metrics_util::layers::Layer;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, PoisonError};
+
+/// Label value recorded in place of values past a label's cardinality cap.
+pub const OVERFLOW_LABEL_VALUE: &str = "other";
+
+/// Normalizes metric names to lowercase with `_` separators, adds a required
+/// prefix and caps the number of distinct values of each metric's labels.
+///
+/// Every metric that had to be changed is logged once as a warning, so the
+/// offending call sites can be fixed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct NamingPolicy {
+    // Prefix added to metric names not already starting with it, usually the
+    // service name.
+    pub prefix: Option<String>,
+
+    // Maximum number of distinct values recorded for a label of a metric,
+    // keyed by label name. Values past the cap are recorded as `other`, each
+    // metric has its own cap.
+    pub label_cardinality: BTreeMap<String, usize>,
+}
+
+impl<R> Layer<R> for NamingPolicy {
+    type Output = NamingPolicyRecorder<R>;
+
+    fn layer(&self, inner: R) -> Self::Output {
+        NamingPolicyRecorder {
+            inner,
+            policy: self.clone(),
+            label_values: Mutex::default(),
+            reported: Mutex::default(),
+        }
+    }
+}
+
+pub struct NamingPolicyRecorder<R> {
+    inner: R,
+    policy: NamingPolicy,
+    /// Values seen per metric name and label name.
+    label_values: Mutex<HashMap<(String, String), HashSet<String>>>,
+    reported: Mutex<HashSet<String>>,
+}
+
+impl<R> NamingPolicyRecorder<R> {
+    fn normalize_name(&self, name: &str) -> Option<String> {
+        let mut normalized: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        if let Some(prefix) = &self.policy.prefix {
+            let has_prefix = normalized
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.starts_with('_'));
+
+            if !has_prefix {
+                normalized = format!("{prefix}_{normalized}");
+            }
+        }
+
+        if normalized == name {
+            return None;
+        }
+
+        self.report_once(name.to_string(), || {
+            tracing::warn!(
+                metric = name,
+                normalized,
+                "metric name violates naming policy"
+            );
+        });
+
+        Some(normalized)
+    }
+
+    fn cap_label(&self, name: &str, label: &Label) -> Option<Label> {
+        let cap = *self.policy.label_cardinality.get(label.key())?;
+
+        let mut label_values = self
+            .label_values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let values = label_values
+            .entry((name.to_string(), label.key().to_string()))
+            .or_default();
+
+        if values.contains(label.value()) {
+            return None;
+        }
+
+        if values.len() < cap {
+            values.insert(label.value().to_string());
+            return None;
+        }
+
+        drop(label_values);
+
+        self.report_once(format!("{name}:{}", label.key()), || {
+            tracing::warn!(
+                metric = name,
+                label = label.key(),
+                cap,
+                "label exceeds its cardinality cap, recording as `{}`",
+                OVERFLOW_LABEL_VALUE
+            );
+        });
+
+        Some(Label::new(label.key().to_string(), OVERFLOW_LABEL_VALUE))
+    }
+
+    /// Returns the key rewritten to follow the policy, or `None` if it
+    /// already does.
+    fn apply(&self, key: &Key) -> Option<Key> {
+        let name = self.normalize_name(key.name());
+
+        // Only allocated once a label is capped.
+        let mut labels: Option<Vec<Label>> = None;
+        for (i, label) in key.labels().enumerate() {
+            match self.cap_label(key.name(), label) {
+                Some(capped) => labels
+                    .get_or_insert_with(|| {
+                        key.labels().take(i).cloned().collect()
+                    })
+                    .push(capped),
+                None => {
+                    if let Some(labels) = &mut labels {
+                        labels.push(label.clone());
+                    }
+                }
+            }
+        }
+
+        if name.is_none() && labels.is_none() {
+            return None;
+        }
+
+        let name = name.unwrap_or_else(|| key.name().to_string());
+        let labels = labels.unwrap_or_else(|| key.labels().cloned().collect());
+
+        Some(Key::from_parts(name, labels))
+    }
+
+    fn apply_key_name(&self, key_name: KeyName) -> KeyName {
+        match self.normalize_name(key_name.as_str()) {
+            Some(name) => KeyName::from(name),
+            None => key_name,
+        }
+    }
+
+    fn report_once(&self, id: String, report: impl FnOnce()) {
+        let first = self
+            .reported
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id);
+
+        if first {
+            report();
+        }
+    }
+}
+
+impl<R: Recorder> Recorder for NamingPolicyRecorder<R> {
+    fn describe_counter(
+        &self,
+        key_name: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+    ) {
+        self.inner.describe_counter(
+            self.apply_key_name(key_name),
+            unit,
+            description,
+        )
+    }
+
+    fn describe_gauge(
+        &self,
+        key_name: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+    ) {
+        self.inner.describe_gauge(
+            self.apply_key_name(key_name),
+            unit,
+            description,
+        )
+    }
+
+    fn describe_histogram(
+        &self,
+        key_name: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+    ) {
+        self.inner.describe_histogram(
+            self.apply_key_name(key_name),
+            unit,
+            description,
+        )
+    }
+
+    fn register_counter(&self, key: &Key, metadata: &Metadata<'_>) -> Counter {
+        match self.apply(key) {
+            Some(key) => self.inner.register_counter(&key, metadata),
+            None => self.inner.register_counter(key, metadata),
+        }
+    }
+
+    fn register_gauge(&self, key: &Key, metadata: &Metadata<'_>) -> Gauge {
+        match self.apply(key) {
+            Some(key) => self.inner.register_gauge(&key, metadata),
+            None => self.inner.register_gauge(key, metadata),
+        }
+    }
+
+    fn register_histogram(
+        &self,
+        key: &Key,
+        metadata: &Metadata<'_>,
+    ) -> Histogram {
+        match self.apply(key) {
+            Some(key) => self.inner.register_histogram(&key, metadata),
+            None => self.inner.register_histogram(key, metadata),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    #[test]
+    fn test_naming_policy() {
+        let policy = NamingPolicy {
+            prefix: Some("orb".to_string()),
+            label_cardinality: BTreeMap::from([("user".to_string(), 2)]),
+        };
+
+        let prometheus = PrometheusBuilder::new().build_recorder();
+        let handle = prometheus.handle();
+        let recorder = policy.layer(prometheus);
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("Http.Requests").increment(1);
+            metrics::counter!("orb_signups").increment(1);
+
+            for user in ["a", "b", "c", "d"] {
+                metrics::counter!("orb_logins", "user" => user).increment(1);
+            }
+            metrics::counter!("orb_logouts", "user" => "d").increment(1);
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains("orb_http_requests 1"));
+        assert!(rendered.contains("orb_signups 1"));
+        assert!(rendered.contains(r#"orb_logins{user="a"} 1"#));
+        assert!(rendered.contains(r#"orb_logins{user="b"} 1"#));
+        assert!(rendered.contains(r#"orb_logins{user="other"} 2"#));
+        assert!(rendered.contains(r#"orb_logouts{user="d"} 1"#));
+    }
+}
@@ -1,9 +1,51 @@
-use metrics_exporter_prometheus::{BuildError, PrometheusBuilder};
+use metrics_exporter_prometheus::{
+    BuildError, Matcher, PrometheusBuilder, PrometheusHandle,
+    PrometheusRecorder,
+};
+use metrics_util::MetricKindMask;
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, time::Duration};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{mpsc, OnceLock};
+use std::time::Instant;
+use std::{fs, io};
+use std::{net::SocketAddr, thread, time::Duration};
+
+use crate::exporter::{self, record_request};
+
+/// Default for how often idle metrics are dropped and histograms are drained,
+/// matching the exporter's own default.
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Bounds how long dropping a [`PrometheusShutdownHandle`] waits for the
+/// final push, on top of the client's own timeout.
+const FINAL_PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Push gateway of the first recorder built, pushed to by
+/// [`PrometheusBattery::flush`].
+static PUSH_GATEWAY: OnceLock<PushGateway> = OnceLock::new();
 
 pub struct PrometheusBattery;
 
+#[derive(Debug, thiserror::Error)]
+pub enum PrometheusError {
+    #[error(transparent)]
+    Build(#[from] BuildError),
+
+    #[error("failed to read push gateway TLS file: {0}")]
+    TlsFile(#[from] io::Error),
+
+    #[error("invalid push gateway TLS configuration: {0}")]
+    Tls(#[from] reqwest::Error),
+
+    #[error("push gateway client certificate and key must be set together")]
+    IncompleteClientIdentity,
+
+    #[error("invalid push gateway proxy: {0}")]
+    Proxy(reqwest::Error),
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum PrometheusExporterConfig {
@@ -19,32 +61,603 @@ pub enum PrometheusExporterConfig {
         interval: Duration,
         username: Option<String>,
         password: Option<String>,
+
+        // PEM encoded CA certificate trusted in addition to the system roots.
+        #[serde(default)]
+        ca_cert: Option<PathBuf>,
+
+        // PEM encoded client certificate and PKCS#8 key for mutual TLS.
+        #[serde(default)]
+        client_cert: Option<PathBuf>,
+        #[serde(default)]
+        client_key: Option<PathBuf>,
+
+        // Proxy URL the push gateway is reached through, instead of
+        // `HTTPS_PROXY` or `HTTP_PROXY`.
+        #[serde(default)]
+        proxy: Option<String>,
+
+        // Headers sent with every push, overriding those of
+        // `TELEMETRY_EXPORTER_HEADERS`, e.g. a bearer token.
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+
+        // How long a push may take, `interval` by default so that a hung
+        // gateway doesn't delay the next push.
+        #[serde(default)]
+        timeout: Option<Duration>,
     },
 
+    // Only install the recorder, leaving it to the application to serve the
+    // metrics rendered by the returned handle, e.g. on its own router.
+    Handle,
+
     #[allow(dead_code)]
     Unconfigured,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PrometheusConfig {
+    pub exporter: Option<PrometheusExporterConfig>,
+
+    // Labels added to every exported metric, e.g. service, env, version or
+    // pod, so callsites don't have to repeat them.
+    pub global_labels: BTreeMap<String, String>,
+
+    pub buckets: PrometheusBuckets,
+
+    // Removes metrics that haven't been updated for a while, so ephemeral
+    // label sets don't grow the scrape payload forever.
+    pub idle_timeout: Option<PrometheusIdleTimeout>,
+
+    // How often idle metrics are removed and histograms are drained.
+    // Defaults to 5 seconds.
+    pub upkeep_interval: Option<Duration>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PrometheusBuckets {
+    // Bucket bounds for every histogram without a more specific override.
+    // Histograms without any buckets are rendered as summaries.
+    pub default: Option<Vec<f64>>,
+
+    // Bucket bounds for histograms whose name starts with the given prefix.
+    pub prefixes: BTreeMap<String, Vec<f64>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrometheusIdleTimeout {
+    pub timeout: Duration,
+
+    // Metric kinds the timeout applies to, all of them when empty.
+    #[serde(default)]
+    pub kinds: Vec<MetricKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl PrometheusIdleTimeout {
+    fn mask(&self) -> MetricKindMask {
+        if self.kinds.is_empty() {
+            return MetricKindMask::ALL;
+        }
+
+        self.kinds
+            .iter()
+            .fold(MetricKindMask::NONE, |mask, kind| match kind {
+                MetricKind::Counter => mask | MetricKindMask::COUNTER,
+                MetricKind::Gauge => mask | MetricKindMask::GAUGE,
+                MetricKind::Histogram => mask | MetricKindMask::HISTOGRAM,
+            })
+    }
+}
+
 impl PrometheusBattery {
+    /// Installs the recorder globally. Metrics aren't pushed one final time
+    /// on shutdown, see [`Self::init_with_handle`].
     pub fn init(
         exporter_config: Option<PrometheusExporterConfig>,
-    ) -> Result<(), BuildError> {
-        let mut builder = PrometheusBuilder::new();
+    ) -> Result<(), PrometheusError> {
+        Self::init_with_handle(exporter_config)?.detach();
+
+        Ok(())
+    }
 
-        builder = match exporter_config {
-            Some(PrometheusExporterConfig::HttpListener { listen_address }) => {
-                builder.with_http_listener(listen_address)
+    /// Like [`Self::init`], returning the handle that pushes the metrics one
+    /// final time when dropped in `PushGateway` mode.
+    pub fn init_with_handle(
+        exporter_config: Option<PrometheusExporterConfig>,
+    ) -> Result<PrometheusShutdownHandle, PrometheusError> {
+        Self::init_with_config(PrometheusConfig {
+            exporter: exporter_config,
+            ..Default::default()
+        })
+    }
+
+    pub fn init_with_config(
+        config: PrometheusConfig,
+    ) -> Result<PrometheusShutdownHandle, PrometheusError> {
+        let (recorder, shutdown_handle) = Self::build(config)?;
+
+        metrics::set_global_recorder(recorder).map_err(BuildError::from)?;
+
+        Ok(shutdown_handle)
+    }
+
+    /// Pushes the metrics to the push gateway now, waiting at most `timeout`
+    /// for the push to finish. Returns `true` if the push succeeded, or if
+    /// the installed recorder doesn't push.
+    pub fn flush(timeout: Duration) -> bool {
+        let Some(push_gateway) = PUSH_GATEWAY.get() else {
+            return true;
+        };
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(push_gateway.push_once(timeout));
+        });
+
+        rx.recv_timeout(timeout).unwrap_or(false)
+    }
+
+    /// Builds the recorder and starts its exporter without installing the
+    /// recorder globally, e.g. to combine it with other recorders.
+    pub(crate) fn build(
+        config: PrometheusConfig,
+    ) -> Result<(PrometheusRecorder, PrometheusShutdownHandle), PrometheusError>
+    {
+        let builder = builder(&config)?;
+        let upkeep_interval = config.upkeep_interval.unwrap_or(UPKEEP_INTERVAL);
+
+        match config.exporter {
+            Some(PrometheusExporterConfig::Handle) => {
+                let recorder = builder.build_recorder();
+                let handle = recorder.handle();
+
+                spawn(run_upkeep(handle.clone(), upkeep_interval))?;
+
+                Ok((
+                    recorder,
+                    PrometheusShutdownHandle {
+                        handle: Some(handle),
+                        push_gateway: None,
+                    },
+                ))
             }
             Some(PrometheusExporterConfig::PushGateway {
                 endpoint,
                 interval,
                 username,
                 password,
-            }) => builder
-                .with_push_gateway(endpoint, interval, username, password)?,
-            _ => builder,
+                ca_cert,
+                client_cert,
+                client_key,
+                proxy,
+                headers,
+                timeout,
+            }) => {
+                let endpoint = reqwest::Url::parse(&endpoint).map_err(|e| {
+                    BuildError::InvalidPushGatewayEndpoint(e.to_string())
+                })?;
+
+                let ca_cert = match ca_cert {
+                    Some(path) => {
+                        Some(reqwest::Certificate::from_pem(&fs::read(path)?)?)
+                    }
+                    None => None,
+                };
+
+                let identity = match (client_cert, client_key) {
+                    (Some(cert), Some(key)) => {
+                        Some(reqwest::Identity::from_pkcs8_pem(
+                            &fs::read(cert)?,
+                            &fs::read(key)?,
+                        )?)
+                    }
+                    (None, None) => None,
+                    _ => return Err(PrometheusError::IncompleteClientIdentity),
+                };
+
+                let proxy = proxy
+                    .as_deref()
+                    .map(exporter::proxy)
+                    .transpose()
+                    .map_err(PrometheusError::Proxy)?;
+
+                let client = PushGatewayClient {
+                    ca_cert,
+                    identity,
+                    proxy,
+                    headers: exporter::exporter_headers(headers),
+                    timeout: timeout.unwrap_or(interval),
+                };
+
+                // Surface misconfiguration now rather than on first push
+                client.build()?;
+
+                let recorder = builder.build_recorder();
+                let push_gateway = PushGateway {
+                    endpoint,
+                    username,
+                    password,
+                    client,
+                    handle: recorder.handle(),
+                };
+
+                spawn(push_gateway.clone().run(interval, upkeep_interval))?;
+                let _ = PUSH_GATEWAY.set(push_gateway.clone());
+
+                Ok((
+                    recorder,
+                    PrometheusShutdownHandle {
+                        handle: Some(push_gateway.handle.clone()),
+                        push_gateway: Some(push_gateway),
+                    },
+                ))
+            }
+            exporter_config => {
+                let builder = match exporter_config {
+                    Some(PrometheusExporterConfig::HttpListener {
+                        listen_address,
+                    }) => builder.with_http_listener(listen_address),
+                    _ => builder,
+                };
+
+                // `build` spawns the upkeep task and must run inside a
+                // runtime, so this mirrors what `PrometheusBuilder::install`
+                // does.
+                let recorder = match tokio::runtime::Handle::try_current() {
+                    Ok(handle) => {
+                        let (recorder, exporter) = {
+                            let _guard = handle.enter();
+                            builder.build()?
+                        };
+                        handle.spawn(exporter);
+
+                        recorder
+                    }
+                    Err(_) => {
+                        let runtime = new_runtime()?;
+                        let (recorder, exporter) = {
+                            let _guard = runtime.enter();
+                            builder.build()?
+                        };
+                        spawn_runtime_thread(runtime, exporter)?;
+
+                        recorder
+                    }
+                };
+
+                Ok((
+                    recorder,
+                    PrometheusShutdownHandle {
+                        handle: None,
+                        push_gateway: None,
+                    },
+                ))
+            }
+        }
+    }
+}
+
+/// Applies everything but the exporter from `config` to a new builder.
+fn builder(config: &PrometheusConfig) -> Result<PrometheusBuilder, BuildError> {
+    let mut builder = config
+        .global_labels
+        .iter()
+        .fold(PrometheusBuilder::new(), |builder, (key, value)| {
+            builder.add_global_label(key, value)
+        });
+
+    if let Some(idle_timeout) = &config.idle_timeout {
+        builder = builder
+            .idle_timeout(idle_timeout.mask(), Some(idle_timeout.timeout));
+    }
+
+    if let Some(upkeep_interval) = config.upkeep_interval {
+        builder = builder.upkeep_timeout(upkeep_interval);
+    }
+
+    if let Some(buckets) = &config.buckets.default {
+        builder = builder.set_buckets(buckets)?;
+    }
+
+    for (prefix, buckets) in &config.buckets.prefixes {
+        builder = builder
+            .set_buckets_for_metric(Matcher::Prefix(prefix.clone()), buckets)?;
+    }
+
+    Ok(builder)
+}
+
+/// `PrometheusShutdownHandle` pushes the metrics one final time when dropped
+/// in `PushGateway` mode, so short-lived jobs don't lose their last interval.
+///
+/// It can be attached to a `TracingShutdownHandle` to tear both pipelines
+/// down together.
+#[must_use]
+pub struct PrometheusShutdownHandle {
+    handle: Option<PrometheusHandle>,
+    push_gateway: Option<PushGateway>,
+}
+
+impl PrometheusShutdownHandle {
+    /// Returns the handle used to render the metrics in the Prometheus text
+    /// format, e.g. to serve them from an existing `GET /metrics` route.
+    ///
+    /// Only available in `Handle` and `PushGateway` modes; the HTTP listener
+    /// renders the metrics itself.
+    pub fn handle(&self) -> Option<&PrometheusHandle> {
+        self.handle.as_ref()
+    }
+
+    /// Drops the handle without pushing the metrics one final time.
+    pub fn detach(mut self) {
+        self.push_gateway = None;
+    }
+}
+
+impl Drop for PrometheusShutdownHandle {
+    fn drop(&mut self) {
+        let Some(push_gateway) = self.push_gateway.take() else {
+            return;
+        };
+
+        tracing::info!("Pushing metrics to push gateway before shutdown");
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(push_gateway.push_once(FINAL_PUSH_TIMEOUT));
+        });
+
+        if rx.recv_timeout(FINAL_PUSH_TIMEOUT).is_err() {
+            tracing::warn!(
+                timeout = ?FINAL_PUSH_TIMEOUT,
+                "Final metrics push did not finish in time"
+            );
+        }
+    }
+}
+
+#[derive(Clone)]
+struct PushGateway {
+    endpoint: reqwest::Url,
+    username: Option<String>,
+    password: Option<String>,
+    client: PushGatewayClient,
+    handle: PrometheusHandle,
+}
+
+#[derive(Clone)]
+struct PushGatewayClient {
+    ca_cert: Option<reqwest::Certificate>,
+    identity: Option<reqwest::Identity>,
+    proxy: Option<reqwest::Proxy>,
+    headers: reqwest::header::HeaderMap,
+    timeout: Duration,
+}
+
+impl PushGatewayClient {
+    fn build(&self) -> reqwest::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .default_headers(self.headers.clone());
+
+        if let Some(ca_cert) = &self.ca_cert {
+            builder = builder.add_root_certificate(ca_cert.clone());
+        }
+
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+
+        builder.build()
+    }
+}
+
+impl PushGateway {
+    /// Pushes once from a runtime of its own, so that it works both inside
+    /// and outside of an async context.
+    fn push_once(&self, timeout: Duration) -> bool {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                tracing::error!(
+                    "failed to create runtime for metrics push: {e}"
+                );
+                return false;
+            }
+        };
+
+        let client = PushGatewayClient {
+            timeout: timeout.min(self.client.timeout),
+            ..self.client.clone()
+        };
+        match client.build() {
+            Ok(client) => runtime.block_on(self.push(&client)),
+            Err(e) => {
+                tracing::error!("failed to create push gateway client: {e}");
+                false
+            }
+        }
+    }
+
+    async fn run(self, interval: Duration, upkeep_interval: Duration) {
+        tokio::spawn(run_upkeep(self.handle.clone(), upkeep_interval));
+
+        let client = match self.client.build() {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("failed to create push gateway client: {e}");
+                return;
+            }
+        };
+
+        loop {
+            tokio::time::sleep(interval).await;
+            let _ = self.push(&client).await;
+        }
+    }
+
+    /// Returns whether the push gateway accepted the metrics.
+    async fn push(&self, client: &reqwest::Client) -> bool {
+        let body = self.handle.render();
+        let size = body.len();
+
+        let mut request = client.put(self.endpoint.clone()).body(body);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        let start = Instant::now();
+        let result = request.send().await;
+        record_request(
+            "prometheus_push_gateway",
+            result.as_ref().ok().map(reqwest::Response::status),
+            start.elapsed(),
+            size,
+        );
+
+        match result {
+            Ok(response) if !response.status().is_success() => {
+                tracing::error!(
+                    status = %response.status(),
+                    "unexpected status after pushing metrics to push gateway"
+                );
+                false
+            }
+            Ok(_) => true,
+            Err(e) => {
+                tracing::error!("error sending request to push gateway: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Recorders installed without an exporter need upkeep to be driven by the
+/// caller.
+async fn run_upkeep(handle: PrometheusHandle, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        handle.run_upkeep();
+    }
+}
+
+/// Spawns `future` on the current tokio runtime, or on a dedicated runtime
+/// thread when called from outside of one.
+fn spawn<F>(future: F) -> Result<(), PrometheusError>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        handle.spawn(future);
+        return Ok(());
+    }
+
+    spawn_runtime_thread(new_runtime()?, future)
+}
+
+fn new_runtime() -> Result<tokio::runtime::Runtime, PrometheusError> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| BuildError::FailedToCreateRuntime(e.to_string()).into())
+}
+
+fn spawn_runtime_thread<F>(
+    runtime: tokio::runtime::Runtime,
+    future: F,
+) -> Result<(), PrometheusError>
+where
+    F: Future + Send + 'static,
+{
+    thread::Builder::new()
+        .name("telemetry-batteries-prometheus".to_string())
+        .spawn(move || {
+            runtime.block_on(future);
+        })
+        .map_err(|e| BuildError::FailedToCreateRuntime(e.to_string()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_labels() {
+        let config = PrometheusConfig {
+            global_labels: BTreeMap::from([(
+                "service".to_string(),
+                "test_service".to_string(),
+            )]),
+            ..Default::default()
+        };
+
+        let recorder = builder(&config).unwrap().build_recorder();
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("test_counter").increment(1);
+        });
+
+        assert!(recorder
+            .handle()
+            .render()
+            .contains(r#"test_counter{service="test_service"} 1"#));
+    }
+
+    #[test]
+    fn test_prefix_buckets() {
+        let config = PrometheusConfig {
+            buckets: PrometheusBuckets {
+                default: None,
+                prefixes: BTreeMap::from([(
+                    "latency".to_string(),
+                    vec![0.0001, 0.001],
+                )]),
+            },
+            ..Default::default()
+        };
+
+        let recorder = builder(&config).unwrap().build_recorder();
+        metrics::with_local_recorder(&recorder, || {
+            metrics::histogram!("latency_seconds").record(0.0005);
+            metrics::histogram!("size_bytes").record(100.0);
+        });
+
+        let rendered = recorder.handle().render();
+        assert!(rendered.contains(r#"latency_seconds_bucket{le="0.001"} 1"#));
+        assert!(rendered.contains(r#"size_bytes{quantile="0.5"}"#));
+    }
+
+    #[test]
+    fn test_idle_timeout_mask() {
+        let mut idle_timeout = PrometheusIdleTimeout {
+            timeout: Duration::from_secs(60),
+            kinds: vec![],
         };
+        assert_eq!(idle_timeout.mask(), MetricKindMask::ALL);
 
-        builder.install()
+        idle_timeout.kinds = vec![MetricKind::Counter, MetricKind::Gauge];
+        assert_eq!(
+            idle_timeout.mask(),
+            MetricKindMask::COUNTER | MetricKindMask::GAUGE
+        );
     }
 }
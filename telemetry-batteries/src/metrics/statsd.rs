@@ -1,7 +1,75 @@
-use metrics_exporter_statsd::{StatsdBuilder, StatsdError};
+use cadence::{BufferedUdpMetricSink, MetricSink, QueuingMetricSink};
+use metrics_exporter_statsd::{StatsdBuilder, StatsdError, StatsdRecorder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::UdpSocket;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use std::{env, io, thread};
+
+use crate::resource::container::container_id;
+
+/// Set by the Datadog admission controller or the downward API to the pod
+/// UID, see <https://docs.datadoghq.com/developers/dogstatsd/#origin-detection-over-udp>.
+const DD_ENTITY_ID: &str = "DD_ENTITY_ID";
+const ENTITY_ID_TAG: &str = "dd.internal.entity_id";
+
+/// Sink of the first recorder built, flushed by [`StatsdBattery::flush`].
+static SINK: OnceLock<Arc<QueuingMetricSink>> = OnceLock::new();
 
 pub struct StatsdBattery;
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StatsdConfig {
+    pub host: String,
+    pub port: u16,
+    pub queue_size: usize,
+    pub buffer_size: usize,
+    pub prefix: Option<String>,
+
+    // Tags appended to every metric, e.g. env, service and version. Labels
+    // recorded on a metric are sent as DogStatsD tags alongside these.
+    pub default_tags: BTreeMap<String, String>,
+
+    pub histogram_type: StatsdHistogramType,
+
+    // Tags every metric with `DD_ENTITY_ID` when it is set, and sends the
+    // container id with every metric when running in a container, so the
+    // agent can attribute metrics to the right pod when several share a node.
+    // Off by default, since the agent then tags the metrics with the pod's
+    // tags, which can change their series; set it to `true` to opt in.
+    pub origin_detection: bool,
+}
+
+// How `metrics` histograms are sent to the agent. Datadog only aggregates
+// percentiles across hosts correctly for distributions.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsdHistogramType {
+    #[default]
+    Histogram,
+    Distribution,
+    Timer,
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 8125,
+            queue_size: 5000,
+            buffer_size: 256,
+            prefix: None,
+            default_tags: BTreeMap::new(),
+            histogram_type: StatsdHistogramType::default(),
+            origin_detection: false,
+        }
+    }
+}
+
 impl StatsdBattery {
     pub fn init(
         host: &str,
@@ -10,13 +78,170 @@ impl StatsdBattery {
         buffer_size: usize,
         prefix: Option<&str>,
     ) -> Result<(), StatsdError> {
-        let recorder = StatsdBuilder::from(host, port)
-            .with_queue_size(queue_size)
-            .with_buffer_size(buffer_size)
-            .build(prefix)?;
+        Self::init_with_config(StatsdConfig {
+            host: host.to_string(),
+            port,
+            queue_size,
+            buffer_size,
+            prefix: prefix.map(ToString::to_string),
+            ..Default::default()
+        })
+    }
+
+    pub fn init_with_config(config: StatsdConfig) -> Result<(), StatsdError> {
+        let recorder = Self::build(&config)?;
 
         metrics::set_global_recorder(recorder)?;
 
         Ok(())
     }
+
+    /// Sends the metrics buffered by the installed recorder, waiting at most
+    /// `timeout` for the queued ones to reach the buffer first. Metrics are
+    /// otherwise only sent once `buffer_size` bytes are buffered, which a
+    /// short-lived process may never reach.
+    pub fn flush(timeout: Duration) -> io::Result<()> {
+        let Some(sink) = SINK.get() else {
+            return Ok(());
+        };
+
+        let start = Instant::now();
+        while sink.queued() > 0 && start.elapsed() < timeout {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        sink.flush()
+    }
+
+    /// Builds the recorder without installing it globally, e.g. to combine
+    /// it with other recorders.
+    pub(crate) fn build(
+        config: &StatsdConfig,
+    ) -> Result<StatsdRecorder, StatsdError> {
+        let mut builder =
+            StatsdBuilder::from(config.host.as_str(), config.port)
+                .with_queue_size(config.queue_size)
+                .with_buffer_size(config.buffer_size);
+
+        builder = match config.histogram_type {
+            StatsdHistogramType::Histogram => builder,
+            StatsdHistogramType::Distribution => {
+                builder.histogram_is_distribution()
+            }
+            StatsdHistogramType::Timer => builder.histogram_is_timer(),
+        };
+
+        if config.origin_detection {
+            if let Some(entity_id) =
+                env::var(DD_ENTITY_ID).ok().filter(|id| !id.is_empty())
+            {
+                builder = builder.with_default_tag(ENTITY_ID_TAG, entity_id);
+            }
+        }
+
+        // Kept so that buffered metrics can be flushed on demand.
+        let container = config.origin_detection.then(container_id).flatten();
+        let sink = Arc::new(udp_sink(config, container.as_deref())?);
+        let _ = SINK.set(Arc::clone(&sink));
+        builder = builder.with_sink(SharedSink(sink));
+
+        config
+            .default_tags
+            .iter()
+            .fold(builder, |builder, (key, value)| {
+                builder.with_default_tag(key, value)
+            })
+            .build(config.prefix.as_deref())
+    }
+}
+
+/// Builds the sink the recorder would use, appending the DogStatsD container
+/// field to every metric if `container_id` is set.
+fn udp_sink(
+    config: &StatsdConfig,
+    container_id: Option<&str>,
+) -> io::Result<QueuingMetricSink> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_nonblocking(true)?;
+
+    let udp_sink = BufferedUdpMetricSink::with_capacity(
+        (config.host.as_str(), config.port),
+        socket,
+        config.buffer_size,
+    )
+    .map_err(io::Error::other)?;
+
+    let sink = match container_id {
+        Some(container_id) => QueuingMetricSink::with_capacity(
+            ContainerIdSink {
+                inner: udp_sink,
+                suffix: format!("|c:{container_id}"),
+            },
+            config.queue_size,
+        ),
+        None => QueuingMetricSink::with_capacity(udp_sink, config.queue_size),
+    };
+
+    Ok(sink)
+}
+
+/// Lets [`SINK`] be shared with the recorder.
+struct SharedSink(Arc<QueuingMetricSink>);
+
+impl MetricSink for SharedSink {
+    fn emit(&self, metric: &str) -> io::Result<usize> {
+        self.0.emit(metric)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Appends `suffix` to the metrics written to `inner`.
+struct ContainerIdSink<S> {
+    inner: S,
+    suffix: String,
+}
+
+impl<S: MetricSink> MetricSink for ContainerIdSink<S> {
+    fn emit(&self, metric: &str) -> io::Result<usize> {
+        self.inner.emit(&format!("{metric}{}", self.suffix))
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct CapturingSink(Mutex<Vec<String>>);
+
+    impl MetricSink for &CapturingSink {
+        fn emit(&self, metric: &str) -> io::Result<usize> {
+            self.0.lock().unwrap().push(metric.to_string());
+            Ok(metric.len())
+        }
+    }
+
+    #[test]
+    fn test_container_id_sink() {
+        let captured = CapturingSink::default();
+        let sink = ContainerIdSink {
+            inner: &captured,
+            suffix: "|c:abc123".to_string(),
+        };
+
+        sink.emit("requests:1|c|#env:prod").unwrap();
+
+        assert_eq!(
+            *captured.0.lock().unwrap(),
+            vec!["requests:1|c|#env:prod|c:abc123".to_string()]
+        );
+    }
 }
@@ -0,0 +1,230 @@
+//! Host-wide CPU, memory, disk and network metrics, for agents running on
+//! bare metal where no node exporter collects them.
+//!
+//! Disk I/O counters are read from `/proc/diskstats`, so only on Linux.
+
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::{io, thread, time::Duration};
+use sysinfo::{Disks, Networks, System};
+
+pub struct SystemMetricsBattery;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SystemMetricsConfig {
+    // How often the metrics are collected. CPU usage is averaged over this
+    // interval.
+    pub interval: Duration,
+}
+
+impl Default for SystemMetricsConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+        }
+    }
+}
+
+impl SystemMetricsBattery {
+    /// Starts collecting system metrics on a background thread, recorded
+    /// through the globally installed recorder.
+    pub fn init(
+        config: SystemMetricsConfig,
+    ) -> io::Result<SystemMetricsShutdownHandle> {
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        thread::Builder::new()
+            .name("telemetry-batteries-system-metrics".to_string())
+            .spawn(move || {
+                let mut collector = Collector::new();
+
+                // CPU usage is computed between two refreshes, the first one
+                // alone would report 0.
+                thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+
+                loop {
+                    collector.collect();
+
+                    match stopped.recv_timeout(config.interval) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            })?;
+
+        Ok(SystemMetricsShutdownHandle { _stop: stop })
+    }
+}
+
+/// `SystemMetricsShutdownHandle` stops the collector when dropped.
+#[must_use]
+pub struct SystemMetricsShutdownHandle {
+    _stop: mpsc::Sender<()>,
+}
+
+struct Collector {
+    system: System,
+    disks: Disks,
+    networks: Networks,
+}
+
+impl Collector {
+    fn new() -> Self {
+        let mut system = System::new();
+        system.refresh_cpu_usage();
+
+        Self {
+            system,
+            disks: Disks::new_with_refreshed_list(),
+            networks: Networks::new_with_refreshed_list(),
+        }
+    }
+
+    fn collect(&mut self) {
+        self.system.refresh_cpu_usage();
+        self.system.refresh_memory();
+        self.disks.refresh();
+        self.networks.refresh();
+
+        metrics::gauge!("system.cpu.usage").set(self.system.global_cpu_usage());
+
+        let load = System::load_average();
+        metrics::gauge!("system.load.1").set(load.one);
+        metrics::gauge!("system.load.5").set(load.five);
+        metrics::gauge!("system.load.15").set(load.fifteen);
+
+        metrics::gauge!("system.memory.total")
+            .set(self.system.total_memory() as f64);
+        metrics::gauge!("system.memory.used")
+            .set(self.system.used_memory() as f64);
+        metrics::gauge!("system.swap.total")
+            .set(self.system.total_swap() as f64);
+        metrics::gauge!("system.swap.used").set(self.system.used_swap() as f64);
+
+        for disk in self.disks.list() {
+            let mount = disk.mount_point().to_string_lossy().into_owned();
+
+            metrics::gauge!("system.disk.total", "mount" => mount.clone())
+                .set(disk.total_space() as f64);
+            metrics::gauge!("system.disk.available", "mount" => mount)
+                .set(disk.available_space() as f64);
+        }
+
+        #[cfg(target_os = "linux")]
+        for io in disk_io() {
+            let device = io.device;
+
+            metrics::counter!("system.disk.reads", "device" => device.clone())
+                .absolute(io.reads);
+            metrics::counter!(
+                "system.disk.read_bytes",
+                "device" => device.clone()
+            )
+            .absolute(io.read_bytes);
+            metrics::counter!("system.disk.writes", "device" => device.clone())
+                .absolute(io.writes);
+            metrics::counter!("system.disk.written_bytes", "device" => device)
+                .absolute(io.written_bytes);
+        }
+
+        for (interface, data) in self.networks.list() {
+            metrics::counter!(
+                "system.network.received_bytes",
+                "interface" => interface.clone()
+            )
+            .absolute(data.total_received());
+            metrics::counter!(
+                "system.network.transmitted_bytes",
+                "interface" => interface.clone()
+            )
+            .absolute(data.total_transmitted());
+        }
+    }
+}
+
+/// I/O counters of a block device since boot.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+struct DiskIo {
+    device: String,
+    reads: u64,
+    read_bytes: u64,
+    writes: u64,
+    written_bytes: u64,
+}
+
+/// The I/O counters of the whole disks, partitions would count the same I/O
+/// twice. Loop and RAM devices are left out.
+#[cfg(target_os = "linux")]
+fn disk_io() -> Vec<DiskIo> {
+    let Ok(diskstats) = std::fs::read_to_string("/proc/diskstats") else {
+        return Vec::new();
+    };
+
+    parse_diskstats(&diskstats)
+        .into_iter()
+        .filter(|io| {
+            !io.device.starts_with("loop")
+                && !io.device.starts_with("ram")
+                && std::path::Path::new("/sys/block").join(&io.device).exists()
+        })
+        .collect()
+}
+
+/// Parses `/proc/diskstats`, see
+/// <https://www.kernel.org/doc/Documentation/ABI/testing/procfs-diskstats>.
+#[cfg(target_os = "linux")]
+fn parse_diskstats(diskstats: &str) -> Vec<DiskIo> {
+    // The kernel counts in 512 byte sectors whatever the device's own size.
+    const SECTOR_SIZE: u64 = 512;
+
+    diskstats
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let counter = |index: usize| fields.get(index)?.parse::<u64>().ok();
+
+            Some(DiskIo {
+                device: fields.get(2)?.to_string(),
+                reads: counter(3)?,
+                read_bytes: counter(5)? * SECTOR_SIZE,
+                writes: counter(7)?,
+                written_bytes: counter(9)? * SECTOR_SIZE,
+            })
+        })
+        .collect()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diskstats() {
+        let diskstats = "\
+ 259       0 nvme0n1 1200 30 88000 400 900 12 64000 700 0 1000 1100 0 0 0 0
+ 259       1 nvme0n1p1 100 0 2000 10 0 0 0 0 0 10 10 0 0 0 0
+   7       0 loop0 bad";
+
+        assert_eq!(
+            parse_diskstats(diskstats),
+            vec![
+                DiskIo {
+                    device: "nvme0n1".to_string(),
+                    reads: 1200,
+                    read_bytes: 88000 * 512,
+                    writes: 900,
+                    written_bytes: 64000 * 512,
+                },
+                DiskIo {
+                    device: "nvme0n1p1".to_string(),
+                    reads: 100,
+                    read_bytes: 2000 * 512,
+                    writes: 0,
+                    written_bytes: 0,
+                },
+            ]
+        );
+    }
+}
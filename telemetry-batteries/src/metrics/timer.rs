@@ -0,0 +1,35 @@
+use metrics::Histogram;
+use std::time::Instant;
+
+/// Records the time elapsed since it was created into a histogram, in
+/// seconds, when dropped.
+///
+/// ```
+/// use telemetry_batteries::metrics::timer::Timer;
+///
+/// fn handle_request() {
+///     let _timer = Timer::new(metrics::histogram!("request.duration"));
+///
+///     // ...
+/// }
+/// ```
+#[must_use]
+pub struct Timer {
+    histogram: Histogram,
+    start: Instant,
+}
+
+impl Timer {
+    pub fn new(histogram: Histogram) -> Self {
+        Self {
+            histogram,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.histogram.record(self.start.elapsed());
+    }
+}
@@ -0,0 +1,144 @@
+//! Cloud attributes, queried from the metadata services of ECS, GCE and EC2.
+
+use opentelemetry::KeyValue;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::env;
+use std::time::Duration;
+
+const METADATA_HOST: &str = "http://169.254.169.254";
+
+/// Bounds each metadata request, outside a cloud the link-local address
+/// doesn't answer at all.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Returns the `cloud.*` and `host.*` attributes of the first metadata
+/// service that answers, or nothing outside ECS, GCE and EC2.
+///
+/// Blocks for up to 1.5 seconds when the metadata services don't answer.
+pub(crate) fn detect() -> Vec<KeyValue> {
+    // The metadata services are link-local, an egress proxy can't reach them.
+    let Ok(client) = Client::builder()
+        .no_proxy()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+    else {
+        return Vec::new();
+    };
+
+    if let Ok(uri) = env::var("ECS_CONTAINER_METADATA_URI_V4") {
+        return ecs(&client, &uri).unwrap_or_default();
+    }
+
+    gce(&client).or_else(|| ec2(&client)).unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EcsTask {
+    cluster: String,
+    #[serde(rename = "TaskARN")]
+    task_arn: String,
+    family: String,
+    availability_zone: Option<String>,
+}
+
+fn ecs(client: &Client, uri: &str) -> Option<Vec<KeyValue>> {
+    let task: EcsTask = client
+        .get(format!("{uri}/task"))
+        .send()
+        .and_then(|response| response.error_for_status()?.json())
+        .ok()?;
+
+    let mut attributes = vec![
+        KeyValue::new("cloud.provider", "aws"),
+        KeyValue::new("cloud.platform", "aws_ecs"),
+        KeyValue::new("aws.ecs.cluster.arn", task.cluster),
+        KeyValue::new("aws.ecs.task.arn", task.task_arn),
+        KeyValue::new("aws.ecs.task.family", task.family),
+    ];
+    if let Some(zone) = task.availability_zone {
+        attributes.push(KeyValue::new("cloud.availability_zone", zone));
+    }
+
+    Some(attributes)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GceInstance {
+    id: u64,
+    // `projects/<number>/zones/<zone>`
+    zone: String,
+    // `projects/<number>/machineTypes/<type>`
+    machine_type: String,
+}
+
+fn gce(client: &Client) -> Option<Vec<KeyValue>> {
+    let instance: GceInstance = client
+        .get(format!(
+            "{METADATA_HOST}/computeMetadata/v1/instance/?recursive=true"
+        ))
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .and_then(|response| response.error_for_status()?.json())
+        .ok()?;
+
+    let zone = instance.zone.rsplit('/').next()?.to_string();
+    let region = zone.rsplit_once('-').map(|(region, _)| region.to_string());
+    let machine_type = instance.machine_type.rsplit('/').next()?.to_string();
+
+    let mut attributes = vec![
+        KeyValue::new("cloud.provider", "gcp"),
+        KeyValue::new("cloud.platform", "gcp_compute_engine"),
+        KeyValue::new("cloud.availability_zone", zone),
+        KeyValue::new("host.id", instance.id.to_string()),
+        KeyValue::new("host.type", machine_type),
+    ];
+    if let Some(region) = region {
+        attributes.push(KeyValue::new("cloud.region", region));
+    }
+
+    Some(attributes)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ec2Identity {
+    account_id: String,
+    region: String,
+    availability_zone: String,
+    instance_id: String,
+    instance_type: String,
+}
+
+fn ec2(client: &Client) -> Option<Vec<KeyValue>> {
+    // IMDSv2 requires a session token.
+    let token = client
+        .put(format!("{METADATA_HOST}/latest/api/token"))
+        .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .send()
+        .and_then(|response| response.error_for_status()?.text())
+        .ok()?;
+
+    let identity: Ec2Identity = client
+        .get(format!(
+            "{METADATA_HOST}/latest/dynamic/instance-identity/document"
+        ))
+        .header("X-aws-ec2-metadata-token", token)
+        .send()
+        .and_then(|response| response.error_for_status()?.json())
+        .ok()?;
+
+    Some(vec![
+        KeyValue::new("cloud.provider", "aws"),
+        KeyValue::new("cloud.platform", "aws_ec2"),
+        KeyValue::new("cloud.account.id", identity.account_id),
+        KeyValue::new("cloud.region", identity.region),
+        KeyValue::new("cloud.availability_zone", identity.availability_zone),
+        KeyValue::new("host.id", identity.instance_id),
+        KeyValue::new("host.type", identity.instance_type),
+    ])
+}
@@ -0,0 +1,109 @@
+//! Container id detection from the cgroup and mount tables of the process.
+
+use std::fs;
+use std::sync::OnceLock;
+
+const CGROUP_PATH: &str = "/proc/self/cgroup";
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// Returns the id of the container the process runs in, if any.
+///
+/// The id is found in `/proc/self/cgroup` with cgroup v1, and in
+/// `/proc/self/mountinfo` with cgroup v2, where the container runtime mounts
+/// files from the container's directory. It is only looked up once.
+pub fn container_id() -> Option<String> {
+    static CONTAINER_ID: OnceLock<Option<String>> = OnceLock::new();
+
+    CONTAINER_ID.get_or_init(read_container_id).clone()
+}
+
+fn read_container_id() -> Option<String> {
+    let from_cgroup = fs::read_to_string(CGROUP_PATH)
+        .ok()
+        .and_then(|cgroup| container_id_from_cgroup(&cgroup));
+
+    from_cgroup.or_else(|| {
+        fs::read_to_string(MOUNTINFO_PATH)
+            .ok()
+            .and_then(|mountinfo| container_id_from_mountinfo(&mountinfo))
+    })
+}
+
+fn container_id_from_cgroup(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .flat_map(|path| path.rsplit('/'))
+        .find_map(container_id_from_segment)
+}
+
+fn container_id_from_mountinfo(mountinfo: &str) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(3))
+        .find_map(|root| {
+            let (_, rest) = root.split_once("/containers/")?;
+            container_id_from_segment(rest.split('/').next()?)
+        })
+}
+
+/// Extracts the id from a path segment such as `docker-<id>.scope` or
+/// `cri-containerd-<id>.scope`.
+fn container_id_from_segment(segment: &str) -> Option<String> {
+    let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+    let id = segment.rsplit('-').next()?;
+
+    if is_container_id(id) {
+        return Some(id.to_string());
+    }
+
+    // ECS Fargate ids are `<32 hex digits>-<10 digits>`.
+    let (task, suffix) = segment.rsplit_once('-')?;
+    let task = task.rsplit('-').next()?;
+    let is_fargate = task.len() == 32
+        && task.bytes().all(|b| b.is_ascii_hexdigit())
+        && suffix.len() == 10
+        && suffix.bytes().all(|b| b.is_ascii_digit());
+
+    is_fargate.then(|| format!("{task}-{suffix}"))
+}
+
+fn is_container_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str =
+        "3726184226f5d3147c25fdeab5b60097e378e8a720503a5e19ecfdf29f869860";
+
+    #[test]
+    fn test_container_id() {
+        let docker = format!("12:pids:/docker/{ID}\n0::/docker/{ID}\n");
+        assert_eq!(container_id_from_cgroup(&docker).as_deref(), Some(ID));
+
+        let kubepods = format!(
+            "0::/kubepods.slice/kubepods-pod1.slice/cri-containerd-{ID}.scope\n"
+        );
+        assert_eq!(container_id_from_cgroup(&kubepods).as_deref(), Some(ID));
+
+        let fargate = "11:cpu:/ecs/55091c13b9/55091c13b9b64a10a6f1d6e8b1f7c73d-1234567890\n";
+        assert_eq!(
+            container_id_from_cgroup(fargate).as_deref(),
+            Some("55091c13b9b64a10a6f1d6e8b1f7c73d-1234567890")
+        );
+
+        assert_eq!(container_id_from_cgroup("0::/\n"), None);
+
+        let mountinfo = format!(
+            "1 2 0:3 / / rw - overlay overlay rw\n\
+             4 5 254:1 /var/lib/docker/containers/{ID}/hostname /etc/hostname rw - ext4 /dev/vda1 rw\n"
+        );
+        assert_eq!(
+            container_id_from_mountinfo(&mountinfo).as_deref(),
+            Some(ID)
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Host attributes, resolved from the OS.
+
+use opentelemetry::KeyValue;
+use std::{env, fs};
+
+const HOSTNAME_PATH: &str = "/proc/sys/kernel/hostname";
+
+/// Returns the `host.name`, `host.arch` and `os.type` attributes.
+pub(crate) fn detect() -> Vec<KeyValue> {
+    let mut attributes = vec![
+        KeyValue::new("host.arch", arch(env::consts::ARCH)),
+        KeyValue::new("os.type", os_type(env::consts::OS)),
+    ];
+    if let Some(hostname) = hostname() {
+        attributes.insert(0, KeyValue::new("host.name", hostname));
+    }
+
+    attributes
+}
+
+fn hostname() -> Option<String> {
+    fs::read_to_string(HOSTNAME_PATH)
+        .ok()
+        .map(|hostname| hostname.trim().to_string())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .filter(|hostname| !hostname.is_empty())
+}
+
+/// Maps Rust's architecture names to the semantic conventions' ones.
+fn arch(arch: &'static str) -> &'static str {
+    match arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "x86",
+        "arm" => "arm32",
+        "powerpc64" => "ppc64",
+        other => other,
+    }
+}
+
+/// Maps Rust's OS names to the semantic conventions' ones.
+fn os_type(os: &'static str) -> &'static str {
+    match os {
+        "macos" => "darwin",
+        other => other,
+    }
+}
@@ -0,0 +1,50 @@
+//! Kubernetes attributes, exposed to the pod through the downward API.
+
+use opentelemetry::KeyValue;
+use std::{env, fs};
+
+const NAMESPACE_PATH: &str =
+    "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Returns the `k8s.*` attributes of the pod, or nothing outside Kubernetes.
+///
+/// The namespace, pod and node names are read from the `POD_NAMESPACE`,
+/// `POD_NAME` and `NODE_NAME` env vars, which the pod spec sets from the
+/// downward API:
+///
+/// ```yaml
+/// env:
+///   - name: NODE_NAME
+///     valueFrom:
+///       fieldRef:
+///         fieldPath: spec.nodeName
+/// ```
+///
+/// Without them, the namespace falls back to the service account's and the
+/// pod name to the hostname.
+pub(crate) fn detect() -> Vec<KeyValue> {
+    if env::var_os("KUBERNETES_SERVICE_HOST").is_none() {
+        return Vec::new();
+    }
+
+    let namespace = non_empty_var("POD_NAMESPACE").or_else(|| {
+        fs::read_to_string(NAMESPACE_PATH)
+            .ok()
+            .map(|namespace| namespace.trim().to_string())
+    });
+    let pod = non_empty_var("POD_NAME").or_else(|| non_empty_var("HOSTNAME"));
+    let node = non_empty_var("NODE_NAME");
+
+    [
+        ("k8s.namespace.name", namespace),
+        ("k8s.pod.name", pod),
+        ("k8s.node.name", node),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some(KeyValue::new(key, value?)))
+    .collect()
+}
+
+fn non_empty_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
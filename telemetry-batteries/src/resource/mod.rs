@@ -0,0 +1,203 @@
+//! Detection of the environment the service runs in, so that spans and logs
+//! are attributed to the right pod, container and cloud instance without
+//! every service plumbing env vars through.
+
+#[cfg(feature = "resource-detectors")]
+mod cloud;
+pub mod container;
+#[cfg(feature = "resource-detectors")]
+mod host;
+#[cfg(feature = "resource-detectors")]
+mod kubernetes;
+
+#[cfg(feature = "datadog")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "datadog")]
+use serde::{Deserialize, Serialize};
+
+/// Which resource detectors run, all of them but the cloud one by default.
+/// Nothing is detected without the `resource-detectors` feature.
+#[cfg(feature = "datadog")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EnrichmentConfig {
+    // `k8s.*` attributes from the downward API.
+    pub kubernetes: bool,
+    // `container.id` from the cgroup of the process.
+    pub container: bool,
+    // `cloud.*` and `host.*` attributes from the ECS, GCE or EC2 metadata
+    // services, e.g. the availability zone. Off by default, since the probes
+    // block startup for up to 1.5 seconds outside a cloud.
+    pub cloud: bool,
+    // `host.name`, `host.arch` and `os.type`.
+    pub host: bool,
+}
+
+#[cfg(feature = "datadog")]
+impl Default for EnrichmentConfig {
+    fn default() -> Self {
+        Self {
+            kubernetes: true,
+            container: true,
+            cloud: false,
+            host: true,
+        }
+    }
+}
+
+/// Returns the attributes of the environment: the Kubernetes pod, the
+/// container id and the host, named after the OpenTelemetry semantic
+/// conventions. See [`detect_with_config`] for the cloud instance.
+#[cfg(feature = "resource-detectors")]
+pub fn detect() -> Vec<KeyValue> {
+    detect_with_config(&EnrichmentConfig::default())
+}
+
+/// Like [`detect`], running only the detectors enabled in `config`.
+///
+/// With `cloud` enabled, the metadata services are queried on a thread of
+/// their own, which blocks for up to 1.5 seconds outside a cloud. Call it
+/// once at startup.
+#[cfg(feature = "resource-detectors")]
+pub fn detect_with_config(config: &EnrichmentConfig) -> Vec<KeyValue> {
+    // The blocking HTTP client can't be used from within an async runtime.
+    let cloud = config.cloud.then(|| std::thread::spawn(cloud::detect));
+
+    let mut attributes = Vec::new();
+    if config.kubernetes {
+        attributes.extend(kubernetes::detect());
+    }
+    if config.container {
+        if let Some(id) = container::container_id() {
+            attributes.push(KeyValue::new("container.id", id));
+        }
+    }
+
+    let cloud = cloud
+        .map(|cloud| cloud.join().unwrap_or_default())
+        .unwrap_or_default();
+    if config.host {
+        // The cloud's `host.*` attributes are more specific.
+        attributes.extend(host::detect().into_iter().filter(|attribute| {
+            !cloud.iter().any(|other| other.key == attribute.key)
+        }));
+    }
+    attributes.extend(cloud);
+
+    attributes
+}
+
+/// Env var of the attributes returned by [`from_env`].
+#[cfg(feature = "datadog")]
+pub const OTEL_RESOURCE_ATTRIBUTES_ENV: &str = "OTEL_RESOURCE_ATTRIBUTES";
+
+/// Returns the attributes of `OTEL_RESOURCE_ATTRIBUTES`, formatted as
+/// `key=value,key2=value2` with percent-encoded values. Malformed pairs are
+/// skipped.
+#[cfg(feature = "datadog")]
+pub fn from_env() -> Vec<KeyValue> {
+    std::env::var(OTEL_RESOURCE_ATTRIBUTES_ENV)
+        .map(|value| parse_attributes(&value))
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "datadog")]
+fn parse_attributes(value: &str) -> Vec<KeyValue> {
+    parse_key_values(value)
+        .map(|(key, value)| KeyValue::new(key, value))
+        .collect()
+}
+
+/// Parses comma separated `key=value` pairs with percent-encoded values,
+/// the format of `OTEL_RESOURCE_ATTRIBUTES`. Malformed pairs are skipped.
+#[cfg(any(feature = "datadog", feature = "metrics-exporters"))]
+pub(crate) fn parse_key_values(
+    value: &str,
+) -> impl Iterator<Item = (String, String)> + '_ {
+    value.split(',').filter_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        let key = key.trim();
+        if key.is_empty() {
+            return None;
+        }
+
+        Some((key.to_string(), percent_decode(value.trim())))
+    })
+}
+
+#[cfg(any(feature = "datadog", feature = "metrics-exporters"))]
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Attributes the batteries attach to spans and logs: those of
+/// `OTEL_RESOURCE_ATTRIBUTES`, overriding the ones detected as configured in
+/// `enrichment` with the `resource-detectors` feature.
+///
+/// `service.name` is left out, the batteries take it as a parameter.
+#[cfg(feature = "datadog")]
+pub(crate) fn battery_attributes(
+    enrichment: &EnrichmentConfig,
+) -> Vec<KeyValue> {
+    #[cfg(feature = "resource-detectors")]
+    let mut attributes = detect_with_config(enrichment);
+    #[cfg(not(feature = "resource-detectors"))]
+    let mut attributes: Vec<KeyValue> = {
+        let _ = enrichment;
+        Vec::new()
+    };
+
+    let from_env = from_env();
+    attributes.retain(|attribute| {
+        !from_env.iter().any(|other| other.key == attribute.key)
+    });
+    attributes.extend(from_env);
+    attributes.retain(|attribute| attribute.key.as_str() != "service.name");
+
+    attributes
+}
+
+#[cfg(all(test, feature = "datadog"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attributes() {
+        let attributes = parse_attributes(
+            "deployment.environment=prod, team = platform,\
+             note=a%2Cb%20c%zz,=missing,invalid",
+        );
+
+        assert_eq!(
+            attributes,
+            vec![
+                KeyValue::new("deployment.environment", "prod"),
+                KeyValue::new("team", "platform"),
+                KeyValue::new("note", "a,b c%zz"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Attribute keys of the OpenTelemetry semantic conventions, see
+//! <https://opentelemetry.io/docs/specs/semconv/>, and the span fields the
+//! crate gives a meaning to.
+//!
+//! Tracing takes constants as field names when they are wrapped in braces:
+//!
+//! ```
+//! use telemetry_batteries::semconv::{HTTP_REQUEST_METHOD, HTTP_ROUTE};
+//!
+//! #[tracing::instrument(fields({ HTTP_REQUEST_METHOD } = "GET"))]
+//! fn get_user(id: u64) {
+//!     tracing::info_span!("request", { HTTP_ROUTE } = "/users/{id}");
+//! }
+//! ```
+
+// Fields read by tracing-opentelemetry and the Datadog exporter.
+
+/// Overrides the span name.
+pub const OTEL_NAME: &str = "otel.name";
+/// The span kind: `server`, `client`, `producer`, `consumer` or `internal`.
+pub const OTEL_KIND: &str = "otel.kind";
+/// The span status: `ok` or `error`.
+pub const OTEL_STATUS_CODE: &str = "otel.status_code";
+/// How Datadog renders the span, e.g. `web`, `sql` or `queue`.
+pub const SPAN_TYPE: &str = "span.type";
+
+// HTTP
+
+pub const HTTP_REQUEST_METHOD: &str = "http.request.method";
+pub const HTTP_RESPONSE_STATUS_CODE: &str = "http.response.status_code";
+/// The route template, e.g. `/users/{id}`.
+pub const HTTP_ROUTE: &str = "http.route";
+pub const URL_FULL: &str = "url.full";
+pub const URL_PATH: &str = "url.path";
+pub const URL_QUERY: &str = "url.query";
+pub const URL_SCHEME: &str = "url.scheme";
+pub const SERVER_ADDRESS: &str = "server.address";
+pub const SERVER_PORT: &str = "server.port";
+pub const CLIENT_ADDRESS: &str = "client.address";
+pub const USER_AGENT_ORIGINAL: &str = "user_agent.original";
+
+// Databases and caches
+
+/// The database, e.g. `postgresql` or `redis`.
+pub const DB_SYSTEM: &str = "db.system";
+pub const DB_NAMESPACE: &str = "db.namespace";
+/// The operation, e.g. `SELECT` or `GET`.
+pub const DB_OPERATION_NAME: &str = "db.operation.name";
+pub const DB_STATEMENT: &str = "db.statement";
+/// The cache key, hashed by [`cache_span!`](crate::cache_span!).
+pub const CACHE_KEY: &str = "cache.key";
+/// Whether a cache command found the key.
+pub const CACHE_HIT: &str = "cache.hit";
+
+// Messaging
+
+/// The message broker, e.g. `kafka`, `aws_sqs` or `rabbitmq`.
+pub const MESSAGING_SYSTEM: &str = "messaging.system";
+/// The topic or queue.
+pub const MESSAGING_DESTINATION_NAME: &str = "messaging.destination.name";
+/// `publish`, `receive` or `process`.
+pub const MESSAGING_OPERATION: &str = "messaging.operation";
+pub const MESSAGING_MESSAGE_ID: &str = "messaging.message.id";
+
+// RPC
+
+/// The RPC framework, e.g. `grpc`.
+pub const RPC_SYSTEM: &str = "rpc.system";
+/// The full service name, e.g. `myapp.v1.Users`.
+pub const RPC_SERVICE: &str = "rpc.service";
+pub const RPC_METHOD: &str = "rpc.method";
+pub const RPC_GRPC_STATUS_CODE: &str = "rpc.grpc.status_code";
+
+// Functions as a service
+
+pub const FAAS_INVOCATION_ID: &str = "faas.invocation_id";
+/// Whether the invocation is the first of the process.
+pub const FAAS_COLDSTART: &str = "faas.coldstart";
+
+// Errors
+
+pub const ERROR_TYPE: &str = "error.type";
+pub const EXCEPTION_MESSAGE: &str = "exception.message";
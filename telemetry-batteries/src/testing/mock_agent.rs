@@ -0,0 +1,329 @@
+//! A local stand-in for the Datadog agent, for asserting on what the
+//! [`DatadogBattery`](crate::tracing::datadog::DatadogBattery) exports.
+
+use rmpv::Value;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tiny_http::{Method, Request, Response, Server};
+
+const TRACES_PATH: &str = "/v0.5/traces";
+
+/// Number of fields of a span in the v0.5 payload.
+const SPAN_FIELDS: usize = 12;
+
+/// An HTTP server implementing the agent's `/v0.5/traces` endpoint.
+///
+/// Received payloads are decoded and their spans kept for
+/// [`MockDatadogAgent::received_spans`]. The server stops when dropped.
+///
+/// ```
+/// use std::time::Duration;
+/// use telemetry_batteries::testing::mock_agent::MockDatadogAgent;
+/// use telemetry_batteries::tracing::datadog::DatadogBattery;
+///
+/// # #[tokio::main(flavor = "multi_thread")]
+/// # async fn main() -> std::io::Result<()> {
+/// # std::env::set_var("RUST_LOG", "info");
+/// let agent = MockDatadogAgent::start()?;
+/// let shutdown_handle =
+///     DatadogBattery::init(Some(agent.endpoint()), "my-service", None, false);
+///
+/// tracing::info_span!("request").in_scope(|| {});
+/// shutdown_handle.flush(Duration::from_secs(5));
+///
+/// let spans = agent.wait_for_spans(1, Duration::from_secs(5));
+/// assert_eq!(spans[0].resource, "request");
+/// # Ok(())
+/// # }
+/// ```
+pub struct MockDatadogAgent {
+    endpoint: String,
+    server: Arc<Server>,
+    spans: Arc<(Mutex<Vec<AgentSpan>>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// A span as received by the agent, with the dictionary indices of the
+/// payload resolved to strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentSpan {
+    pub service: String,
+    pub name: String,
+    pub resource: String,
+    pub trace_id: u64,
+    pub span_id: u64,
+    pub parent_id: u64,
+    pub start: i64,
+    pub duration: i64,
+    pub error: i32,
+    pub meta: BTreeMap<String, String>,
+    pub metrics: BTreeMap<String, f64>,
+    pub span_type: String,
+}
+
+impl MockDatadogAgent {
+    /// Starts the agent on a free local port.
+    pub fn start() -> io::Result<Self> {
+        let server = Server::http("127.0.0.1:0").map_err(io::Error::other)?;
+        let addr = server
+            .server_addr()
+            .to_ip()
+            .expect("server listens on an IP address");
+
+        let server = Arc::new(server);
+        let spans = Arc::new((Mutex::default(), Condvar::new()));
+
+        let handle = thread::Builder::new()
+            .name("telemetry-batteries-mock-agent".to_string())
+            .spawn({
+                let server = Arc::clone(&server);
+                let spans = Arc::clone(&spans);
+
+                move || {
+                    for request in server.incoming_requests() {
+                        handle_request(request, &spans);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            endpoint: format!("http://{addr}"),
+            server,
+            spans,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the agent's URL, to be passed as the battery's endpoint.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Returns the spans received so far, in the order they arrived.
+    pub fn received_spans(&self) -> Vec<AgentSpan> {
+        self.spans
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Waits until at least `count` spans were received, or `timeout`
+    /// elapsed, and returns the spans received so far.
+    ///
+    /// Spans are exported in batches, so tests should flush the battery
+    /// before waiting.
+    pub fn wait_for_spans(
+        &self,
+        count: usize,
+        timeout: Duration,
+    ) -> Vec<AgentSpan> {
+        let (spans, received) = &*self.spans;
+        let spans = spans.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let (spans, _) = received
+            .wait_timeout_while(spans, timeout, |spans| spans.len() < count)
+            .unwrap_or_else(PoisonError::into_inner);
+
+        spans.clone()
+    }
+}
+
+impl Drop for MockDatadogAgent {
+    fn drop(&mut self) {
+        self.server.unblock();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn handle_request(
+    mut request: Request,
+    spans: &(Mutex<Vec<AgentSpan>>, Condvar),
+) {
+    if request.url() != TRACES_PATH {
+        let _ = request.respond(Response::empty(404));
+        return;
+    }
+
+    if *request.method() != Method::Put && *request.method() != Method::Post {
+        let _ = request.respond(Response::empty(405));
+        return;
+    }
+
+    let mut body = Vec::new();
+    let decoded = request
+        .as_reader()
+        .read_to_end(&mut body)
+        .ok()
+        .and_then(|_| decode_payload(&body));
+
+    let Some(decoded) = decoded else {
+        let _ = request.respond(
+            Response::from_string("invalid v0.5 payload").with_status_code(400),
+        );
+        return;
+    };
+
+    let (received, notify) = spans;
+    received
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .extend(decoded);
+    notify.notify_all();
+
+    let _ = request.respond(Response::from_string(r#"{"rate_by_service":{}}"#));
+}
+
+/// Decodes a v0.5 payload: a dictionary of strings followed by the traces,
+/// whose spans refer to strings by their index in the dictionary.
+fn decode_payload(body: &[u8]) -> Option<Vec<AgentSpan>> {
+    let payload = rmpv::decode::read_value(&mut &body[..]).ok()?;
+    let [dictionary, traces] = payload.as_array()?.as_slice() else {
+        return None;
+    };
+
+    let dictionary = dictionary
+        .as_array()?
+        .iter()
+        .map(Value::as_str)
+        .collect::<Option<Vec<_>>>()?;
+    let string = |value: &Value| {
+        let index = usize::try_from(value.as_u64()?).ok()?;
+        dictionary.get(index).map(|s| s.to_string())
+    };
+
+    let mut spans = Vec::new();
+    for trace in traces.as_array()? {
+        for span in trace.as_array()? {
+            let span = span.as_array()?;
+            if span.len() != SPAN_FIELDS {
+                return None;
+            }
+
+            let meta = span[9]
+                .as_map()?
+                .iter()
+                .map(|(key, value)| Some((string(key)?, string(value)?)))
+                .collect::<Option<_>>()?;
+            let metrics = span[10]
+                .as_map()?
+                .iter()
+                .map(|(key, value)| Some((string(key)?, value.as_f64()?)))
+                .collect::<Option<_>>()?;
+
+            spans.push(AgentSpan {
+                service: string(&span[0])?,
+                name: string(&span[1])?,
+                resource: string(&span[2])?,
+                trace_id: span[3].as_u64()?,
+                span_id: span[4].as_u64()?,
+                parent_id: span[5].as_u64()?,
+                start: span[6].as_i64()?,
+                duration: span[7].as_i64()?,
+                error: i32::try_from(span[8].as_i64()?).ok()?,
+                meta,
+                metrics,
+                span_type: string(&span[11])?,
+            });
+        }
+    }
+
+    Some(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn put(endpoint: &str, body: &[u8]) -> String {
+        let addr = endpoint.trim_start_matches("http://");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "PUT {TRACES_PATH} HTTP/1.1\r\nHost: {addr}\r\n\
+             Content-Type: application/msgpack\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_decodes_v05_payload() {
+        let span = Value::Array(vec![
+            Value::from(1),
+            Value::from(2),
+            Value::from(3),
+            Value::from(42u64),
+            Value::from(7u64),
+            Value::from(0u64),
+            Value::from(1_000i64),
+            Value::from(500i64),
+            Value::from(0),
+            Value::Map(vec![(Value::from(4), Value::from(5))]),
+            Value::Map(vec![(Value::from(6), Value::from(1.0))]),
+            Value::from(0),
+        ]);
+        let payload = Value::Array(vec![
+            Value::Array(
+                [
+                    "",
+                    "my-service",
+                    "opentelemetry",
+                    "request",
+                    "env",
+                    "test",
+                    "_sampling_priority_v1",
+                ]
+                .into_iter()
+                .map(Value::from)
+                .collect(),
+            ),
+            Value::Array(vec![Value::Array(vec![span])]),
+        ]);
+        let mut body = Vec::new();
+        rmpv::encode::write_value(&mut body, &payload).unwrap();
+
+        let agent = MockDatadogAgent::start().unwrap();
+        let response = put(agent.endpoint(), &body);
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        let spans = agent.wait_for_spans(1, Duration::from_secs(5));
+        assert_eq!(
+            spans,
+            vec![AgentSpan {
+                service: "my-service".to_string(),
+                name: "opentelemetry".to_string(),
+                resource: "request".to_string(),
+                trace_id: 42,
+                span_id: 7,
+                parent_id: 0,
+                start: 1_000,
+                duration: 500,
+                error: 0,
+                meta: BTreeMap::from([("env".to_string(), "test".to_string())]),
+                metrics: BTreeMap::from([(
+                    "_sampling_priority_v1".to_string(),
+                    1.0
+                )]),
+                span_type: String::new(),
+            }]
+        );
+
+        let response = put(agent.endpoint(), b"not msgpack");
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+}
@@ -0,0 +1,473 @@
+//! Helpers for testing code instrumented with `tracing`, enabled with the
+//! `testing` feature.
+
+pub mod mock_agent;
+
+use opentelemetry::trace::{SpanId, Status, TraceId, TracerProvider as _};
+use opentelemetry::Value;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::trace::TracerProvider;
+use std::collections::BTreeMap;
+use std::future::{self, Future};
+use std::io;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use tracing::subscriber::DefaultGuard;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, EnvFilter, Layer, Registry,
+};
+
+use crate::tracing::layers::datadog::{
+    datadog_format_layer_with_writer, DatadogFormat,
+};
+
+/// Filter used when `RUST_LOG` is not set.
+const DEFAULT_TEST_FILTER: &str = "debug";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TestOutput {
+    /// Compact events written through libtest's output capture, so they are
+    /// only shown for failing tests or with `--nocapture`.
+    #[default]
+    Capture,
+
+    /// Pretty-printed events written straight to stderr, shown for every
+    /// test.
+    Pretty,
+}
+
+/// Installs a subscriber for the current thread until the returned guard is
+/// dropped, restoring the previous one.
+///
+/// Unlike the batteries, this never sets the global subscriber, so tests
+/// running in parallel don't conflict. Events emitted on other threads, e.g.
+/// by tasks of a multi-threaded runtime, are not seen.
+pub fn test_subscriber(output: TestOutput) -> DefaultGuard {
+    bridge_log();
+
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(DEFAULT_TEST_FILTER));
+    let registry = Registry::default().with(filter);
+
+    match output {
+        TestOutput::Capture => tracing::subscriber::set_default(
+            registry.with(fmt::layer().compact().with_test_writer()),
+        ),
+        TestOutput::Pretty => tracing::subscriber::set_default(
+            registry.with(fmt::layer().pretty().with_writer(std::io::stderr)),
+        ),
+    }
+}
+
+/// Forwards records of the `log` crate to the current subscriber, as the
+/// batteries do.
+fn bridge_log() {
+    // Fails if a logger is already set, e.g. by an earlier call.
+    let _ = tracing_log::LogTracer::init();
+}
+
+static TEST_TELEMETRY: OnceLock<TestTelemetry> = OnceLock::new();
+
+/// Installs a global subscriber exporting spans to memory, for asserting on
+/// the spans emitted by the code under test.
+///
+/// Every span is recorded regardless of `RUST_LOG`, which only filters the
+/// events written to the test output. The global subscriber can only be set
+/// once, so later calls in the same process return a handle to the same
+/// exporter and [`TestTelemetry::finished_spans`] includes the spans of
+/// every test that ran so far.
+///
+/// Events are also captured in the Datadog JSON format, see
+/// [`TestTelemetry::logs`].
+///
+/// Prefer [`TestTelemetry::scoped`] when several tests assert on telemetry.
+///
+/// # Panics
+/// Panics if another global subscriber was already installed.
+pub fn init_for_tests() -> TestTelemetry {
+    TEST_TELEMETRY
+        .get_or_init(|| {
+            let telemetry = TestTelemetry::new();
+            bridge_log();
+            tracing::subscriber::set_global_default(telemetry.subscriber())
+                .expect("a global subscriber is already installed");
+
+            telemetry
+        })
+        .clone()
+}
+
+/// Handle to the spans exported by [`init_for_tests`] or
+/// [`TestTelemetry::scoped`].
+#[derive(Debug, Clone)]
+pub struct TestTelemetry {
+    spans: Arc<Mutex<Vec<SpanData>>>,
+    provider: TracerProvider,
+    logs: CapturedLogs,
+}
+
+impl TestTelemetry {
+    fn new() -> Self {
+        let spans = Arc::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(InMemorySpanExporter {
+                spans: Arc::clone(&spans),
+            })
+            .build();
+
+        Self {
+            spans,
+            provider,
+            logs: CapturedLogs::default(),
+        }
+    }
+
+    /// Installs a subscriber like [`init_for_tests`] for the current thread
+    /// only, until the returned guard is dropped and the previous subscriber
+    /// is restored.
+    ///
+    /// Each call exports to its own memory, so tests running in parallel
+    /// only see their own spans and events. As with [`test_subscriber`],
+    /// spans and events on other threads, e.g. of tasks running on a
+    /// multi-threaded runtime, are not seen.
+    pub fn scoped() -> ScopedTestTelemetry {
+        let telemetry = Self::new();
+        bridge_log();
+        let guard = tracing::subscriber::set_default(telemetry.subscriber());
+
+        ScopedTestTelemetry {
+            telemetry,
+            _guard: guard,
+        }
+    }
+
+    fn subscriber(&self) -> impl Subscriber + Send + Sync {
+        let filter = EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(DEFAULT_TEST_FILTER));
+
+        let otel_layer = tracing_opentelemetry::OpenTelemetryLayer::new(
+            self.provider.tracer("telemetry-batteries"),
+        );
+        let capture_layer = datadog_format_layer_with_writer(
+            DatadogFormat::new(true),
+            self.logs.clone(),
+        );
+
+        Registry::default()
+            .with(capture_layer.and_then(otel_layer))
+            .with(
+                fmt::layer()
+                    .compact()
+                    .with_test_writer()
+                    .with_filter(filter),
+            )
+    }
+
+    /// Returns the events logged so far.
+    pub fn logs(&self) -> &CapturedLogs {
+        &self.logs
+    }
+
+    /// Returns the spans closed so far, in the order they were closed.
+    pub fn finished_spans(&self) -> Vec<FinishedSpan> {
+        self.spans
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(FinishedSpan::from)
+            .collect()
+    }
+}
+
+/// A [`TestTelemetry`] installed for the current thread, see
+/// [`TestTelemetry::scoped`].
+#[must_use]
+pub struct ScopedTestTelemetry {
+    telemetry: TestTelemetry,
+    _guard: DefaultGuard,
+}
+
+impl Deref for ScopedTestTelemetry {
+    type Target = TestTelemetry;
+
+    fn deref(&self) -> &Self::Target {
+        &self.telemetry
+    }
+}
+
+/// A span exported by [`TestTelemetry`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinishedSpan {
+    pub name: String,
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    pub parent_span_id: Option<SpanId>,
+    pub attributes: BTreeMap<String, Value>,
+    pub status: Status,
+}
+
+impl From<&SpanData> for FinishedSpan {
+    fn from(span: &SpanData) -> Self {
+        let parent_span_id = (span.parent_span_id != SpanId::INVALID)
+            .then_some(span.parent_span_id);
+
+        Self {
+            name: span.name.to_string(),
+            trace_id: span.span_context.trace_id(),
+            span_id: span.span_context.span_id(),
+            parent_span_id,
+            attributes: span
+                .attributes
+                .iter()
+                .map(|kv| (kv.key.to_string(), kv.value.clone()))
+                .collect(),
+            status: span.status.clone(),
+        }
+    }
+}
+
+/// Returns a layer formatting events like the Datadog battery does, and the
+/// handle they are captured in.
+///
+/// Use it to test the log output with a custom subscriber, e.g. one scoped to
+/// a single test.
+pub fn log_capture_layer<S>() -> (impl Layer<S>, CapturedLogs)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    log_capture_layer_with_format(DatadogFormat::new(true))
+}
+
+/// Like [`log_capture_layer`], with a custom format.
+///
+/// Snapshot tests of the log schema use
+/// [`DatadogFormat::with_deterministic_output`] so the captured events don't
+/// change between runs.
+pub fn log_capture_layer_with_format<S>(
+    format: DatadogFormat,
+) -> (impl Layer<S>, CapturedLogs)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let logs = CapturedLogs::default();
+    let layer = datadog_format_layer_with_writer(format, logs.clone());
+
+    (layer, logs)
+}
+
+/// Events captured as the JSON objects written by the Datadog format.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedLogs {
+    events: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl CapturedLogs {
+    /// Returns every event captured so far.
+    pub fn events(&self) -> Vec<serde_json::Value> {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns the events that have `field` set, e.g. `dd.trace_id`.
+    pub fn events_with_field(&self, field: &str) -> Vec<serde_json::Value> {
+        self.events()
+            .into_iter()
+            .filter(|event| event.get(field).is_some())
+            .collect()
+    }
+
+    /// Asserts that an event was logged at `level` with a message containing
+    /// `substring`.
+    ///
+    /// # Panics
+    /// Panics listing the captured messages if there is no such event.
+    #[track_caller]
+    pub fn assert_logged(&self, level: Level, substring: &str) {
+        let events = self.events();
+        let level = level.as_str();
+
+        let logged = events.iter().any(|event| {
+            event["level"] == level
+                && event["message"]
+                    .as_str()
+                    .is_some_and(|message| message.contains(substring))
+        });
+
+        if !logged {
+            let captured: Vec<String> = events
+                .iter()
+                .map(|event| format!("{} {}", event["level"], event["message"]))
+                .collect();
+
+            panic!(
+                "no {level} event containing {substring:?} was logged, \
+                 captured events: {captured:#?}"
+            );
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = CaptureWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        CaptureWriter {
+            buf: Vec::new(),
+            events: Arc::clone(&self.events),
+        }
+    }
+}
+
+/// Buffers a formatted event and adds it to its [`CapturedLogs`] when
+/// dropped.
+pub struct CaptureWriter {
+    buf: Vec<u8>,
+    events: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl io::Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        let events = serde_json::Deserializer::from_slice(&self.buf)
+            .into_iter::<serde_json::Value>()
+            .filter_map(Result::ok);
+
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(events);
+    }
+}
+
+#[derive(Debug)]
+struct InMemorySpanExporter {
+    spans: Arc<Mutex<Vec<SpanData>>>,
+}
+
+impl SpanExporter for InMemorySpanExporter {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        self.spans
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(batch);
+
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finished_spans() {
+        let telemetry = init_for_tests();
+
+        tracing::info_span!("test_parent").in_scope(|| {
+            tracing::info_span!("test_child", user = "alice").in_scope(|| {});
+        });
+
+        let spans = telemetry.finished_spans();
+        let parent = spans.iter().find(|s| s.name == "test_parent").unwrap();
+        let child = spans.iter().find(|s| s.name == "test_child").unwrap();
+
+        assert_eq!(parent.parent_span_id, None);
+        assert_eq!(child.parent_span_id, Some(parent.span_id));
+        assert_eq!(child.trace_id, parent.trace_id);
+        assert_eq!(child.attributes["user"], Value::from("alice"));
+    }
+
+    #[test]
+    fn test_captured_logs() {
+        let telemetry = init_for_tests();
+
+        tracing::info_span!("test_logs").in_scope(|| {
+            tracing::warn!(attempt = 3, "test_logs retrying");
+        });
+
+        let logs = telemetry.logs();
+        logs.assert_logged(Level::WARN, "test_logs retrying");
+
+        let event = logs
+            .events_with_field("dd.trace_id")
+            .into_iter()
+            .find(|event| event["message"] == "test_logs retrying")
+            .unwrap();
+        assert_eq!(event["attempt"], 3);
+    }
+
+    #[test]
+    fn test_scoped() {
+        let outer = TestTelemetry::scoped();
+
+        {
+            let inner = TestTelemetry::scoped();
+            tracing::info_span!("inner").in_scope(|| {});
+            tracing::info!("inner event");
+
+            let spans = inner.finished_spans();
+            assert_eq!(spans.len(), 1);
+            assert_eq!(spans[0].name, "inner");
+            assert_eq!(inner.logs().events().len(), 1);
+        }
+
+        tracing::info_span!("outer").in_scope(|| {});
+
+        let spans = outer.finished_spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "outer");
+        assert!(outer.logs().events().is_empty());
+    }
+
+    #[test]
+    fn test_log_records() {
+        let telemetry = TestTelemetry::scoped();
+
+        log::warn!(target: "hyper::proto", "connection closed");
+
+        telemetry
+            .logs()
+            .assert_logged(Level::WARN, "connection closed");
+    }
+
+    #[test]
+    fn test_deterministic_output() {
+        let (layer, logs) = log_capture_layer_with_format(
+            DatadogFormat::new(false).with_deterministic_output(),
+        );
+        let otel_layer = tracing_opentelemetry::OpenTelemetryLayer::new(
+            TestTelemetry::new().provider.tracer("test"),
+        );
+        let subscriber = Registry::default().with(layer.and_then(otel_layer));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info!(user = "alice", attempt = 1, "done");
+            });
+        });
+
+        assert_eq!(
+            serde_json::to_string(&logs.events()).unwrap(),
+            r#"[{"attempt":1,"dd.span_id":"0","dd.trace_id":"0","level":"INFO","message":"done","target":"telemetry_batteries::testing::tests","timestamp":"1970-01-01T00:00:00+00:00","user":"alice"}]"#
+        );
+    }
+}
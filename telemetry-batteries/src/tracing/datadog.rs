@@ -1,10 +1,19 @@
+use std::time::Duration;
+
+use crate::resource::EnrichmentConfig;
 use crate::tracing::layers::{
-    datadog::datadog_layer, non_blocking_writer_layer,
+    datadog::{
+        datadog_blocking_layer_with_enrichment, datadog_layer_with_enrichment,
+        DEFAULT_AGENT_TIMEOUT,
+    },
+    error_metrics::error_metrics_layer,
+    non_blocking_writer_layer,
 };
 use opentelemetry_datadog::DatadogPropagator;
+use opentelemetry_sdk::runtime::{RuntimeChannel, Tokio};
 use tracing_appender::rolling::RollingFileAppender;
 use tracing_subscriber::{
-    layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+    layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
 };
 
 use super::TracingShutdownHandle;
@@ -14,32 +23,155 @@ pub const DEFAULT_DATADOG_AGENT_ENDPOINT: &str = "http://localhost:8126";
 pub struct DatadogBattery;
 
 impl DatadogBattery {
+    /// Installs the global subscriber. Records of the `log` crate, e.g. from
+    /// dependencies, are forwarded through the same filter and layers.
     pub fn init(
         endpoint: Option<&str>,
         service_name: &str,
         file_appender: Option<RollingFileAppender>,
         location: bool,
+    ) -> TracingShutdownHandle {
+        Self::init_with_runtime(
+            endpoint,
+            service_name,
+            file_appender,
+            location,
+            Tokio,
+        )
+    }
+
+    /// Like [`Self::init`], exporting spans on `runtime`, see
+    /// [`datadog_layer_with_runtime`](crate::tracing::layers::datadog::datadog_layer_with_runtime).
+    pub fn init_with_runtime<R: RuntimeChannel>(
+        endpoint: Option<&str>,
+        service_name: &str,
+        file_appender: Option<RollingFileAppender>,
+        location: bool,
+        runtime: R,
+    ) -> TracingShutdownHandle {
+        Self::init_batch(
+            endpoint,
+            service_name,
+            file_appender,
+            location,
+            runtime,
+            &EnrichmentConfig::default(),
+            DEFAULT_AGENT_TIMEOUT,
+        )
+    }
+
+    /// Like [`Self::init`], detecting the attributes attached to spans and
+    /// logs as configured in `enrichment`, e.g. to opt in to the cloud
+    /// metadata probes.
+    pub fn init_with_enrichment(
+        endpoint: Option<&str>,
+        service_name: &str,
+        file_appender: Option<RollingFileAppender>,
+        location: bool,
+        enrichment: EnrichmentConfig,
+    ) -> TracingShutdownHandle {
+        Self::init_batch(
+            endpoint,
+            service_name,
+            file_appender,
+            location,
+            Tokio,
+            &enrichment,
+            DEFAULT_AGENT_TIMEOUT,
+        )
+    }
+
+    /// Like [`Self::init`], abandoning requests to the agent after
+    /// `agent_timeout`, unless overridden by
+    /// [`DATADOG_TIMEOUT_ENV`](crate::tracing::layers::datadog::DATADOG_TIMEOUT_ENV).
+    pub fn init_with_agent_timeout(
+        endpoint: Option<&str>,
+        service_name: &str,
+        file_appender: Option<RollingFileAppender>,
+        location: bool,
+        agent_timeout: Duration,
+    ) -> TracingShutdownHandle {
+        Self::init_batch(
+            endpoint,
+            service_name,
+            file_appender,
+            location,
+            Tokio,
+            &EnrichmentConfig::default(),
+            agent_timeout,
+        )
+    }
+
+    fn init_batch<R: RuntimeChannel>(
+        endpoint: Option<&str>,
+        service_name: &str,
+        file_appender: Option<RollingFileAppender>,
+        location: bool,
+        runtime: R,
+        enrichment: &EnrichmentConfig,
+        agent_timeout: Duration,
     ) -> TracingShutdownHandle {
         opentelemetry::global::set_text_map_propagator(DatadogPropagator::new());
 
         let endpoint = endpoint.unwrap_or(DEFAULT_DATADOG_AGENT_ENDPOINT);
 
-        let datadog_layer = datadog_layer(service_name, endpoint, location);
+        let datadog_layer = datadog_layer_with_enrichment(
+            service_name,
+            endpoint,
+            location,
+            runtime,
+            enrichment,
+            agent_timeout,
+        );
 
+        Self::install(datadog_layer, file_appender)
+    }
+
+    /// Like [`Self::init`], exporting every span when it ends without an
+    /// async runtime, see
+    /// [`datadog_blocking_layer`](crate::tracing::layers::datadog::datadog_blocking_layer).
+    pub fn init_blocking(
+        endpoint: Option<&str>,
+        service_name: &str,
+        file_appender: Option<RollingFileAppender>,
+        location: bool,
+    ) -> TracingShutdownHandle {
+        opentelemetry::global::set_text_map_propagator(DatadogPropagator::new());
+
+        let endpoint = endpoint.unwrap_or(DEFAULT_DATADOG_AGENT_ENDPOINT);
+
+        let datadog_layer = datadog_blocking_layer_with_enrichment(
+            service_name,
+            endpoint,
+            location,
+            &EnrichmentConfig::default(),
+            DEFAULT_AGENT_TIMEOUT,
+        );
+
+        Self::install(datadog_layer, file_appender)
+    }
+
+    fn install(
+        datadog_layer: impl Layer<Registry> + Send + Sync + 'static,
+        file_appender: Option<RollingFileAppender>,
+    ) -> TracingShutdownHandle {
         if let Some(file_appender) = file_appender {
             let file_writer_layer = non_blocking_writer_layer(file_appender);
 
             let layers = EnvFilter::from_default_env()
+                .and_then(error_metrics_layer())
                 .and_then(datadog_layer)
                 .and_then(file_writer_layer);
 
             tracing_subscriber::registry().with(layers).init();
         } else {
-            let layers = EnvFilter::from_default_env().and_then(datadog_layer);
+            let layers = EnvFilter::from_default_env()
+                .and_then(error_metrics_layer())
+                .and_then(datadog_layer);
             tracing_subscriber::registry().with(layers).init();
         }
 
-        TracingShutdownHandle
+        TracingShutdownHandle::default()
     }
 }
 
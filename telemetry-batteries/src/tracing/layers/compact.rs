@@ -0,0 +1,233 @@
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{
+    FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter,
+};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::tracing::timestamp::TimestampPrecision;
+use crate::tracing::{opentelemetry_span_id, opentelemetry_trace_id};
+
+/// Single line `key=value` output with the Datadog trace ids, for reading
+/// logs in a terminal.
+///
+/// ```text
+/// 2024-05-01T12:00:00.000+00:00  INFO api::users: user created user_id=42 request.path="/users" dd.trace_id=123 dd.span_id=456
+/// ```
+///
+/// Fields of the spans in scope are only written if they are listed in
+/// `span_fields`, prefixed with the span name.
+pub fn compact_layer<S>(span_fields: Vec<String>) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    compact_layer_with_writer(span_fields, std::io::stdout)
+}
+
+pub(crate) fn compact_layer_with_writer<S, W>(
+    span_fields: Vec<String>,
+    make_writer: W,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::Layer::new()
+        .fmt_fields(SelectedFields { names: span_fields })
+        .event_format(CompactFormat)
+        .with_writer(make_writer)
+}
+
+pub struct CompactFormat;
+
+impl<S, N> FormatEvent<S, N> for CompactFormat
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let meta = event.metadata();
+
+        TimestampPrecision::Millis
+            .with_now(|timestamp| write!(writer, "{timestamp} "))?;
+        write!(writer, "{:>5} {}:", meta.level(), meta.target())?;
+
+        let mut visitor = CompactVisitor {
+            writer: &mut writer,
+            result: Ok(()),
+        };
+        event.record(&mut visitor);
+        visitor.result?;
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<N>>()
+                else {
+                    continue;
+                };
+
+                for field in fields.split(' ').filter(|f| !f.is_empty()) {
+                    write!(writer, " {}.{field}", span.name())?;
+                }
+            }
+        }
+
+        if let Some(trace_id) = opentelemetry_trace_id(ctx) {
+            // Truncated like the Datadog format does.
+            write!(writer, " dd.trace_id={}", trace_id as u64)?;
+        }
+
+        if let Some(span_id) = opentelemetry_span_id(ctx) {
+            write!(writer, " dd.span_id={span_id}")?;
+        }
+
+        writeln!(writer)
+    }
+}
+
+/// Formats only the span fields named in `names`.
+struct SelectedFields {
+    names: Vec<String>,
+}
+
+impl<'writer> FormatFields<'writer> for SelectedFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = SelectedVisitor {
+            names: &self.names,
+            writer: &mut writer,
+            separator: "",
+            result: Ok(()),
+        };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = SelectedVisitor {
+            names: &self.names,
+            separator: if current.fields.is_empty() { "" } else { " " },
+            writer: &mut current.as_writer(),
+            result: Ok(()),
+        };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+struct SelectedVisitor<'a, 'writer> {
+    names: &'a [String],
+    writer: &'a mut Writer<'writer>,
+    separator: &'static str,
+    result: fmt::Result,
+}
+
+impl Visit for SelectedVisitor<'_, '_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.result.is_err()
+            || !self.names.iter().any(|name| name == field.name())
+        {
+            return;
+        }
+
+        self.result = write!(
+            self.writer,
+            "{}{}={:?}",
+            self.separator,
+            field.name(),
+            value
+        );
+        self.separator = " ";
+    }
+}
+
+struct CompactVisitor<'a, 'writer> {
+    writer: &'a mut Writer<'writer>,
+    result: fmt::Result,
+}
+
+impl Visit for CompactVisitor<'_, '_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{value}"));
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.result.is_err() {
+            return;
+        }
+
+        self.result = match field.name() {
+            "message" => write!(self.writer, " {value:?}"),
+            // Bridged `log` records, their target is already written.
+            name if name.starts_with("log.") => Ok(()),
+            name => write!(self.writer, " {name}={value:?}"),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    struct OutputWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for OutputWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_compact_line() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer = {
+            let output = Arc::clone(&output);
+            move || OutputWriter(Arc::clone(&output))
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(compact_layer_with_writer(vec!["path".to_string()], writer));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", path = "/users", id = 7);
+            span.in_scope(|| {
+                tracing::info!(user_id = 42, name = "alice", "user created");
+            });
+        });
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let (_timestamp, line) = output.split_once(' ').unwrap();
+        assert_eq!(
+            line,
+            " INFO telemetry_batteries::tracing::layers::compact::tests: \
+             user created user_id=42 name=\"alice\" request.path=\"/users\"\n"
+        );
+    }
+}
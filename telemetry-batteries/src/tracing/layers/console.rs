@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+
+use tracing::{Level, Metadata, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use wasm_bindgen::JsValue;
+use web_sys::console;
+
+use super::datadog::{datadog_format_layer_with_writer, DatadogFormat};
+
+/// Writes events to the browser console in the Datadog JSON format, as
+/// objects so that their fields can be expanded in the devtools.
+///
+/// ERROR events go to `console.error`, WARN events to `console.warn` and
+/// the others to `console.log`.
+///
+/// The batteries and the other writers rely on threads, which the browser
+/// doesn't have, so this layer is meant to be installed on its own:
+///
+/// ```ignore
+/// use telemetry_batteries::tracing::layers::console::console_layer;
+/// use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+///
+/// tracing_subscriber::registry().with(console_layer()).init();
+/// ```
+pub fn console_layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    datadog_format_layer_with_writer(
+        DatadogFormat::new(false),
+        MakeConsoleWriter,
+    )
+}
+
+struct MakeConsoleWriter;
+
+impl<'a> MakeWriter<'a> for MakeConsoleWriter {
+    type Writer = ConsoleWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        ConsoleWriter {
+            level: Level::INFO,
+            line: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        ConsoleWriter {
+            level: *meta.level(),
+            line: Vec::new(),
+        }
+    }
+}
+
+/// Buffers a formatted event and logs it to the console when dropped.
+struct ConsoleWriter {
+    level: Level,
+    line: Vec<u8>,
+}
+
+impl Write for ConsoleWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ConsoleWriter {
+    fn drop(&mut self) {
+        let line = String::from_utf8_lossy(&self.line);
+        let line = line.trim_end();
+        if line.is_empty() {
+            return;
+        }
+
+        let value = js_sys::JSON::parse(line)
+            .unwrap_or_else(|_| JsValue::from_str(line));
+
+        match self.level {
+            Level::ERROR => console::error_1(&value),
+            Level::WARN => console::warn_1(&value),
+            _ => console::log_1(&value),
+        }
+    }
+}
@@ -1,22 +1,87 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+#[cfg(feature = "datadog")]
 use std::time::Duration;
 
-use chrono::Utc;
-use opentelemetry_datadog::ApiVersion;
-use opentelemetry_sdk::trace::{Config, Sampler};
+#[cfg(feature = "datadog")]
+use http::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(feature = "datadog")]
+use opentelemetry::trace::TracerProvider as _;
+#[cfg(feature = "datadog")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "datadog")]
+use opentelemetry_datadog::{ApiVersion, DatadogExporter};
+#[cfg(feature = "datadog")]
+use opentelemetry_http::HttpClient;
+#[cfg(feature = "datadog")]
+use opentelemetry_sdk::runtime::{RuntimeChannel, Tokio};
+#[cfg(feature = "datadog")]
+use opentelemetry_sdk::trace::{
+    BatchSpanProcessor, Config, Sampler, SimpleSpanProcessor, SpanProcessor,
+    TracerProvider,
+};
+#[cfg(feature = "datadog")]
+use opentelemetry_sdk::Resource;
 use serde::ser::SerializeMap;
-use serde::Serializer;
-use tracing::{Event, Subscriber};
-use tracing_serde::AsSerde;
+use serde::{Serialize, Serializer};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_log::NormalizeEvent;
+#[cfg(feature = "datadog")]
+use tracing_subscriber::filter::dynamic_filter_fn;
 use tracing_subscriber::fmt::format::Writer;
-use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::{
+    FmtContext, FormatEvent, FormatFields, MakeWriter,
+};
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::{fmt, Layer};
 
+#[cfg(feature = "datadog")]
+use crate::exporter::{exporter_headers, proxy, InstrumentedHttpClient};
+#[cfg(feature = "datadog")]
+use crate::resource;
+#[cfg(feature = "datadog")]
+use crate::resource::container::container_id;
+#[cfg(feature = "datadog")]
+use crate::resource::EnrichmentConfig;
+#[cfg(feature = "datadog")]
 use crate::tracing::id_generator::ReducedIdGenerator;
+#[cfg(feature = "datadog")]
+use crate::tracing::processor::{
+    AttributeFilter, AttributeFilterProcessor, CountingExporter,
+    CountingProcessor, SpanNameProcessor,
+};
+use crate::tracing::timestamp::TimestampPrecision;
+#[cfg(feature = "datadog")]
+use crate::tracing::{
+    init_tracing_enabled_from_env, tracing_enabled, TRACER_PROVIDER,
+};
 use crate::tracing::{
     opentelemetry_span_id, opentelemetry_trace_id, WriteAdapter,
 };
 
+/// Proxy URL the agent is reached through, instead of `HTTPS_PROXY` or
+/// `HTTP_PROXY`.
+#[cfg(feature = "datadog")]
+pub const DATADOG_PROXY_ENV: &str = "TELEMETRY_DATADOG_PROXY";
+
+/// Seconds after which a request to the agent is abandoned, named like the
+/// Datadog tracers' setting. Overrides the timeout passed to the layers,
+/// [`DEFAULT_AGENT_TIMEOUT`] unless set with e.g.
+/// [`datadog_layer_with_agent_timeout`].
+#[cfg(feature = "datadog")]
+pub const DATADOG_TIMEOUT_ENV: &str = "DD_TRACE_AGENT_TIMEOUT";
+
+/// Short enough that a hung agent doesn't back up the span queue until
+/// spans are dropped.
+#[cfg(feature = "datadog")]
+pub const DEFAULT_AGENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[cfg(feature = "datadog")]
+const CONTAINER_ID_HEADER: HeaderName =
+    HeaderName::from_static("datadog-container-id");
+
+#[cfg(feature = "datadog")]
 pub fn datadog_layer<S>(
     service_name: &str,
     endpoint: &str,
@@ -25,29 +90,285 @@ pub fn datadog_layer<S>(
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
 {
-    let tracer_config = Config::default()
-        .with_id_generator(ReducedIdGenerator)
-        .with_sampler(Sampler::AlwaysOn);
+    datadog_layer_with_runtime(service_name, endpoint, location, Tokio)
+}
+
+/// Like [`datadog_layer`], exporting spans in batches on `runtime` instead
+/// of tokio, e.g. `AsyncStd` with the `rt-async-std` feature. Other
+/// executors can be plugged in by implementing [`RuntimeChannel`].
+///
+/// The exporter's HTTP client still needs a tokio reactor, which
+/// async-std's `tokio1` feature or `async-compat` provide.
+#[cfg(feature = "datadog")]
+pub fn datadog_layer_with_runtime<S, R>(
+    service_name: &str,
+    endpoint: &str,
+    location: bool,
+    runtime: R,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    R: RuntimeChannel,
+{
+    datadog_layer_with_enrichment(
+        service_name,
+        endpoint,
+        location,
+        runtime,
+        &EnrichmentConfig::default(),
+        DEFAULT_AGENT_TIMEOUT,
+    )
+}
 
+/// Like [`datadog_layer`], abandoning requests to the agent after
+/// `agent_timeout` instead of [`DEFAULT_AGENT_TIMEOUT`]. The
+/// [`DATADOG_TIMEOUT_ENV`] variable still takes precedence.
+#[cfg(feature = "datadog")]
+pub fn datadog_layer_with_agent_timeout<S>(
+    service_name: &str,
+    endpoint: &str,
+    location: bool,
+    agent_timeout: Duration,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    datadog_layer_with_enrichment(
+        service_name,
+        endpoint,
+        location,
+        Tokio,
+        &EnrichmentConfig::default(),
+        agent_timeout,
+    )
+}
+
+/// Like [`datadog_layer_with_runtime`], detecting the resource attributes
+/// as configured in `enrichment` and abandoning requests to the agent after
+/// `agent_timeout`.
+#[cfg(feature = "datadog")]
+pub(crate) fn datadog_layer_with_enrichment<S, R>(
+    service_name: &str,
+    endpoint: &str,
+    location: bool,
+    runtime: R,
+    enrichment: &EnrichmentConfig,
+    agent_timeout: Duration,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    R: RuntimeChannel,
+{
     // Small hack https://github.com/will-bank/datadog-tracing/blob/30cdfba8d00caa04f6ac8e304f76403a5eb97129/src/tracer.rs#L29
     // Until https://github.com/open-telemetry/opentelemetry-rust-contrib/issues/7 is resolved
     // seems to prevent client reuse and avoid the errors in question
-    let dd_http_client = reqwest::ClientBuilder::new()
+    let mut dd_http_client = reqwest::ClientBuilder::new()
         .pool_idle_timeout(Duration::from_millis(1))
+        .timeout(timeout_from_env(agent_timeout))
+        .default_headers(agent_headers());
+    if let Some(proxy) = agent_proxy() {
+        dd_http_client = dd_http_client.proxy(proxy);
+    }
+
+    let dd_http_client = dd_http_client
+        .build()
+        .expect("Could not init datadog http_client");
+
+    let exporter = datadog_exporter(service_name, endpoint, dd_http_client);
+    let processor = BatchSpanProcessor::builder(exporter, runtime).build();
+
+    datadog_layer_with_processor(processor, location, enrichment)
+}
+
+/// Like [`datadog_layer`], exporting every span synchronously when it ends
+/// instead of in batches, so that no async runtime is needed. Meant for
+/// short-lived tools, where the batch exporter's timer may never fire.
+///
+/// Ending a span blocks until the agent responded, and the layer must not
+/// be built or used from within an async runtime.
+#[cfg(feature = "datadog")]
+pub fn datadog_blocking_layer<S>(
+    service_name: &str,
+    endpoint: &str,
+    location: bool,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    datadog_blocking_layer_with_enrichment(
+        service_name,
+        endpoint,
+        location,
+        &EnrichmentConfig::default(),
+        DEFAULT_AGENT_TIMEOUT,
+    )
+}
+
+/// Like [`datadog_blocking_layer`], abandoning requests to the agent after
+/// `agent_timeout` instead of [`DEFAULT_AGENT_TIMEOUT`]. The
+/// [`DATADOG_TIMEOUT_ENV`] variable still takes precedence.
+#[cfg(feature = "datadog")]
+pub fn datadog_blocking_layer_with_agent_timeout<S>(
+    service_name: &str,
+    endpoint: &str,
+    location: bool,
+    agent_timeout: Duration,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    datadog_blocking_layer_with_enrichment(
+        service_name,
+        endpoint,
+        location,
+        &EnrichmentConfig::default(),
+        agent_timeout,
+    )
+}
+
+/// Like [`datadog_blocking_layer`], detecting the resource attributes as
+/// configured in `enrichment` and abandoning requests to the agent after
+/// `agent_timeout`.
+#[cfg(feature = "datadog")]
+pub(crate) fn datadog_blocking_layer_with_enrichment<S>(
+    service_name: &str,
+    endpoint: &str,
+    location: bool,
+    enrichment: &EnrichmentConfig,
+    agent_timeout: Duration,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let mut dd_http_client = reqwest::blocking::Client::builder()
+        .timeout(timeout_from_env(agent_timeout))
+        .default_headers(agent_headers());
+    if let Some(proxy) = agent_proxy() {
+        dd_http_client = dd_http_client.proxy(proxy);
+    }
+
+    let dd_http_client = dd_http_client
         .build()
         .expect("Could not init datadog http_client");
 
-    let tracer = opentelemetry_datadog::new_pipeline()
-        .with_http_client(dd_http_client)
+    let exporter = datadog_exporter(service_name, endpoint, dd_http_client);
+    let processor = SimpleSpanProcessor::new(Box::new(exporter));
+
+    datadog_layer_with_processor(processor, location, enrichment)
+}
+
+/// Headers sent with every request to the agent.
+#[cfg(feature = "datadog")]
+fn agent_headers() -> HeaderMap {
+    let mut headers = exporter_headers([]);
+
+    // Lets the agent tag spans with the pod they come from.
+    if let Some(container_id) =
+        container_id().and_then(|id| HeaderValue::from_str(&id).ok())
+    {
+        headers.insert(CONTAINER_ID_HEADER, container_id);
+    }
+
+    headers
+}
+
+/// The timeout set in [`DATADOG_TIMEOUT_ENV`] if valid, `configured`
+/// otherwise.
+#[cfg(feature = "datadog")]
+fn timeout_from_env(configured: Duration) -> Duration {
+    std::env::var(DATADOG_TIMEOUT_ENV)
+        .ok()
+        .and_then(|seconds| seconds.trim().parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .filter(|timeout| !timeout.is_zero())
+        .unwrap_or(configured)
+}
+
+/// The proxy set in [`DATADOG_PROXY_ENV`], if any and valid.
+#[cfg(feature = "datadog")]
+fn agent_proxy() -> Option<reqwest::Proxy> {
+    let url = std::env::var(DATADOG_PROXY_ENV)
+        .ok()
+        .filter(|url| !url.is_empty())?;
+
+    match proxy(&url) {
+        Ok(proxy) => Some(proxy),
+        Err(e) => {
+            eprintln!("Ignoring {DATADOG_PROXY_ENV}, invalid proxy URL: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(feature = "datadog")]
+fn datadog_exporter<C: HttpClient + 'static>(
+    service_name: &str,
+    endpoint: &str,
+    http_client: C,
+) -> CountingExporter<DatadogExporter> {
+    let exporter = opentelemetry_datadog::new_pipeline()
+        .with_http_client(InstrumentedHttpClient::new("datadog", http_client))
         .with_agent_endpoint(endpoint)
-        .with_trace_config(tracer_config)
         .with_service_name(service_name)
         .with_api_version(ApiVersion::Version05)
-        .install_batch(opentelemetry_sdk::runtime::Tokio)
-        .expect("failed to install OpenTelemetry datadog tracer, perhaps check which async runtime is being used");
+        .build_exporter()
+        .expect("failed to build OpenTelemetry datadog exporter");
+
+    CountingExporter::new(exporter)
+}
+
+/// Installs a provider exporting spans through `processor` and returns the
+/// otel and format layers.
+#[cfg(feature = "datadog")]
+fn datadog_layer_with_processor<S, P>(
+    processor: P,
+    location: bool,
+    enrichment: &EnrichmentConfig,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    P: SpanProcessor + 'static,
+{
+    // Datadog takes the service name from the exporter, so it is kept out of
+    // the span resource the same way `install_batch` does it.
+    let detected = resource::battery_attributes(enrichment);
+    let resource = Resource::new(
+        Resource::default()
+            .iter()
+            .filter(|(key, _)| key.as_str() != "service.name")
+            .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+            .chain(detected.iter().cloned()),
+    );
+
+    let tracer_config = Config::default()
+        .with_id_generator(ReducedIdGenerator)
+        .with_sampler(Sampler::AlwaysOn)
+        .with_resource(resource);
+
+    // The provider is built here rather than through `install_batch` so that
+    // we keep a handle to it for flushing.
+    let processor = CountingProcessor::new(AttributeFilterProcessor::new(
+        SpanNameProcessor::from_env(processor),
+        AttributeFilter::from_env(),
+    ));
+    let provider = TracerProvider::builder()
+        .with_span_processor(processor)
+        .with_config(tracer_config)
+        .build();
+    let tracer = provider.tracer("telemetry-batteries");
+
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    let _ = TRACER_PROVIDER.set(provider);
 
-    let otel_layer = tracing_opentelemetry::OpenTelemetryLayer::new(tracer);
-    let dd_format_layer = datadog_format_layer(location);
+    // The filter is evaluated for every span, so that span export can be
+    // switched off at runtime.
+    init_tracing_enabled_from_env();
+    let otel_layer = tracing_opentelemetry::OpenTelemetryLayer::new(tracer)
+        .with_filter(dynamic_filter_fn(|_, _| tracing_enabled()));
+    let dd_format_layer = datadog_format_layer_with_writer(
+        DatadogFormat::new(location).with_resource(detected),
+        std::io::stdout,
+    );
 
     dd_format_layer.and_then(otel_layer)
 }
@@ -55,14 +376,155 @@ where
 pub fn datadog_format_layer<S>(location: bool) -> impl Layer<S>
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    datadog_format_layer_with_writer(
+        DatadogFormat::new(location),
+        std::io::stdout,
+    )
+}
+
+pub(crate) fn datadog_format_layer_with_writer<S, W>(
+    format: DatadogFormat,
+    make_writer: W,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
 {
     fmt::Layer::new()
         .json()
-        .event_format(DatadogFormat { location })
+        .event_format(format)
+        .with_writer(make_writer)
+}
+
+/// Events larger than this don't keep their buffer around for the next
+/// event on the thread.
+const MAX_RETAINED_BUFFER: usize = 64 * 1024;
+
+thread_local! {
+    static FORMAT_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Timestamp written by [`DatadogFormat::with_deterministic_output`].
+pub const DETERMINISTIC_TIMESTAMP: &str = "1970-01-01T00:00:00+00:00";
+
+/// How the level of an event is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LevelOutput {
+    /// `level`, e.g. `"INFO"`.
+    #[default]
+    Text,
+
+    /// `severity_number`, following the OpenTelemetry log data model.
+    SeverityNumber,
+
+    /// Both `level` and `severity_number`.
+    Both,
 }
 
 pub struct DatadogFormat {
     location: bool,
+    deterministic: bool,
+    timestamp_precision: TimestampPrecision,
+    level_output: LevelOutput,
+    level_remaps: Vec<LevelRemap>,
+    resource: Vec<(String, String)>,
+}
+
+struct LevelRemap {
+    target: String,
+    from: Level,
+    to: Level,
+}
+
+impl DatadogFormat {
+    pub fn new(location: bool) -> Self {
+        Self {
+            location,
+            deterministic: false,
+            timestamp_precision: TimestampPrecision::default(),
+            level_output: LevelOutput::default(),
+            level_remaps: Vec::new(),
+            resource: Vec::new(),
+        }
+    }
+
+    /// Writes `attributes`, e.g. the detected `resource` ones, as fields of
+    /// every event.
+    #[cfg(feature = "datadog")]
+    pub fn with_resource(
+        mut self,
+        attributes: impl IntoIterator<Item = KeyValue>,
+    ) -> Self {
+        self.resource
+            .extend(attributes.into_iter().map(|attribute| {
+                (attribute.key.to_string(), attribute.value.to_string())
+            }));
+        self
+    }
+
+    /// Sets how the level of events is written, as text by default.
+    pub fn with_level_output(mut self, level_output: LevelOutput) -> Self {
+        self.level_output = level_output;
+        self
+    }
+
+    /// Writes `from` events of `target` and its submodules with level `to`,
+    /// e.g. to demote the warnings of a noisy dependency to INFO.
+    ///
+    /// Only the written level changes, filters still see the original one.
+    /// The first matching remap applies.
+    pub fn with_level_remap(
+        mut self,
+        target: impl Into<String>,
+        from: Level,
+        to: Level,
+    ) -> Self {
+        self.level_remaps.push(LevelRemap {
+            target: target.into(),
+            from,
+            to,
+        });
+        self
+    }
+
+    fn level(&self, meta: &Metadata<'_>) -> Level {
+        let level = *meta.level();
+
+        self.level_remaps
+            .iter()
+            .find(|remap| {
+                remap.from == level
+                    && meta.target().strip_prefix(&remap.target).is_some_and(
+                        |rest| rest.is_empty() || rest.starts_with("::"),
+                    )
+            })
+            .map_or(level, |remap| remap.to)
+    }
+
+    /// Sets the precision of event timestamps, the exact time of every event
+    /// by default.
+    ///
+    /// Coarser precisions are formatted once per tick, e.g.
+    /// [`TimestampPrecision::Millis`] when formatting shows up in profiles.
+    pub fn with_timestamp_precision(
+        mut self,
+        precision: TimestampPrecision,
+    ) -> Self {
+        self.timestamp_precision = precision;
+        self
+    }
+
+    /// Makes the output reproducible for snapshot tests: the timestamp is
+    /// [`DETERMINISTIC_TIMESTAMP`], trace and span ids are `0` and fields are
+    /// sorted by name.
+    ///
+    /// Line numbers still change with the code, so snapshots are usually
+    /// taken without `location`.
+    pub fn with_deterministic_output(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
 }
 
 impl<S, N> FormatEvent<S, N> for DatadogFormat
@@ -79,49 +541,290 @@ where
     where
         S: Subscriber + for<'a> LookupSpan<'a>,
     {
-        let meta = event.metadata();
+        if self.deterministic {
+            // Serializing to a `Value` first sorts the fields by name.
+            let value = self
+                .serialize_event(ctx, event, serde_json::value::Serializer)
+                .map_err(|_| std::fmt::Error)?;
+            let sorted: BTreeMap<_, _> =
+                value.as_object().into_iter().flatten().collect();
+
+            serde_json::to_writer(WriteAdapter::new(&mut writer), &sorted)
+                .map_err(|_| std::fmt::Error)?;
+        } else {
+            FORMAT_BUFFER.with(|buffer| {
+                // Formatting a field may log, in which case the nested event
+                // gets a buffer of its own.
+                let mut nested = Vec::new();
+                let mut borrowed = buffer.try_borrow_mut();
+                let buffer = match &mut borrowed {
+                    Ok(buffer) => &mut **buffer,
+                    Err(_) => &mut nested,
+                };
 
-        let span_id = opentelemetry_span_id(ctx);
-        let trace_id = opentelemetry_trace_id(ctx);
+                buffer.clear();
+                let result = self
+                    .serialize_event(
+                        ctx,
+                        event,
+                        &mut serde_json::Serializer::new(&mut *buffer),
+                    )
+                    .map_err(|_| std::fmt::Error)
+                    .and_then(|()| {
+                        // serde_json only writes valid UTF-8.
+                        let json = std::str::from_utf8(buffer)
+                            .map_err(|_| std::fmt::Error)?;
+                        writer.write_str(json)
+                    });
 
-        let mut visit = || {
-            let mut serializer =
-                serde_json::Serializer::new(WriteAdapter::new(&mut writer));
-            let mut serializer = serializer.serialize_map(None)?;
+                if buffer.capacity() > MAX_RETAINED_BUFFER {
+                    *buffer = Vec::new();
+                }
 
+                result
+            })?;
+        }
+
+        writeln!(writer)
+    }
+}
+
+impl DatadogFormat {
+    fn serialize_event<S, N, Ser>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        event: &Event<'_>,
+        serializer: Ser,
+    ) -> Result<Ser::Ok, Ser::Error>
+    where
+        S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+        N: for<'writer> FormatFields<'writer> + 'static,
+        Ser: Serializer,
+    {
+        // Records bridged from the `log` crate carry their location in
+        // `log.*` fields, which are moved back into the metadata.
+        let normalized = event.normalized_metadata();
+        let meta = normalized.as_ref().unwrap_or_else(|| event.metadata());
+
+        let mut span_id = opentelemetry_span_id(ctx);
+        let mut trace_id = opentelemetry_trace_id(ctx);
+
+        let mut serializer = serializer.serialize_map(None)?;
+
+        if self.deterministic {
+            span_id = span_id.map(|_| 0);
+            trace_id = trace_id.map(|_| 0);
+            serializer.serialize_entry("timestamp", DETERMINISTIC_TIMESTAMP)?;
+        } else {
+            self.timestamp_precision.with_now(|timestamp| {
+                serializer.serialize_entry("timestamp", timestamp)
+            })?;
+        }
+
+        let level = self.level(meta);
+        if self.level_output != LevelOutput::SeverityNumber {
+            serializer.serialize_entry("level", level.as_str())?;
+        }
+        if self.level_output != LevelOutput::Text {
             serializer
-                .serialize_entry("timestamp", &Utc::now().to_rfc3339())?;
-            serializer.serialize_entry("level", &meta.level().as_serde())?;
-            serializer.serialize_entry("target", meta.target())?;
-
-            if self.location {
-                serializer.serialize_entry("line", &meta.line())?;
-                serializer.serialize_entry("file", &meta.file())?;
-                serializer
-                    .serialize_entry("module_path", &meta.module_path())?;
-            }
-
-            let mut visitor = tracing_serde::SerdeMapVisitor::new(serializer);
-            event.record(&mut visitor);
-            serializer = visitor.take_serializer()?;
-
-            if let Some(trace_id) = trace_id {
-                // The opentelemetry-datadog crate truncates the 128-bit trace-id
-                // into a u64 before formatting it.
-                let trace_id = format!("{}", trace_id as u64);
-                serializer.serialize_entry("dd.trace_id", &trace_id)?;
-            }
-
-            if let Some(span_id) = span_id {
-                let span_id = format!("{}", span_id);
-                serializer.serialize_entry("dd.span_id", &span_id)?;
-            }
-
-            serializer.end()
+                .serialize_entry("severity_number", &severity_number(level))?;
+        }
+        serializer.serialize_entry("target", meta.target())?;
+
+        for (key, value) in &self.resource {
+            serializer.serialize_entry(key, value)?;
+        }
+
+        if self.location {
+            serializer.serialize_entry("line", &meta.line())?;
+            serializer.serialize_entry("file", &meta.file())?;
+            serializer.serialize_entry("module_path", &meta.module_path())?;
+        }
+
+        let mut visitor = FieldVisitor {
+            serializer: &mut serializer,
+            state: Ok(()),
+            skip_log_fields: normalized.is_some(),
         };
+        event.record(&mut visitor);
+        visitor.state?;
 
-        visit().map_err(|_| std::fmt::Error)?;
+        if let Some(trace_id) = trace_id {
+            // The opentelemetry-datadog crate truncates the 128-bit trace-id
+            // into a u64 before formatting it.
+            let mut buffer = itoa::Buffer::new();
+            serializer.serialize_entry(
+                "dd.trace_id",
+                buffer.format(trace_id as u64),
+            )?;
+        }
 
-        writeln!(writer)
+        if let Some(span_id) = span_id {
+            let mut buffer = itoa::Buffer::new();
+            serializer.serialize_entry("dd.span_id", buffer.format(span_id))?;
+        }
+
+        serializer.end()
+    }
+}
+
+/// Returns the OpenTelemetry severity number of `level`.
+fn severity_number(level: Level) -> u8 {
+    match level {
+        Level::TRACE => 1,
+        Level::DEBUG => 5,
+        Level::INFO => 9,
+        Level::WARN => 13,
+        Level::ERROR => 17,
+    }
+}
+
+/// Serializes event fields straight into the event's map.
+///
+/// Values are written as they are visited, `Debug` values are formatted
+/// directly into the output instead of an intermediate `String`.
+struct FieldVisitor<'a, M: SerializeMap> {
+    serializer: &'a mut M,
+    state: Result<(), M::Error>,
+    skip_log_fields: bool,
+}
+
+impl<M: SerializeMap> FieldVisitor<'_, M> {
+    fn serialize_entry<V: Serialize + ?Sized>(
+        &mut self,
+        field: &Field,
+        value: &V,
+    ) {
+        if self.skip_log_fields && field.name().starts_with("log.") {
+            return;
+        }
+
+        if self.state.is_ok() {
+            self.state = self.serializer.serialize_entry(field.name(), value);
+        }
+    }
+}
+
+impl<M: SerializeMap> Visit for FieldVisitor<'_, M> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.serialize_entry(field, &value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.serialize_entry(field, &value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.serialize_entry(field, &value);
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.serialize_entry(field, &value);
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        self.serialize_entry(field, &value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.serialize_entry(field, &value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.serialize_entry(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.serialize_entry(field, &DebugValue(value));
+    }
+}
+
+struct DebugValue<'a>(&'a dyn std::fmt::Debug);
+
+impl Serialize for DebugValue<'_> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:?}", self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn format_events(format: DatadogFormat, log: impl FnOnce()) -> Vec<String> {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer = {
+            let output = Arc::clone(&output);
+            move || OutputWriter(Arc::clone(&output))
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(datadog_format_layer_with_writer(format, writer));
+
+        tracing::subscriber::with_default(subscriber, log);
+
+        let output = output.lock().unwrap();
+        String::from_utf8_lossy(&output)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    struct OutputWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for OutputWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_severity_number_and_remap() {
+        let format = DatadogFormat::new(false)
+            .with_level_output(LevelOutput::Both)
+            .with_level_remap("hyper", Level::WARN, Level::INFO);
+
+        let events = format_events(format, || {
+            tracing::warn!(target: "hyper::proto", "connection closed");
+            tracing::warn!(target: "hyperlocal", "not remapped");
+        });
+
+        assert!(events[0].contains(r#""level":"INFO","severity_number":9"#));
+        assert!(events[1].contains(r#""level":"WARN","severity_number":13"#));
+    }
+
+    #[test]
+    fn test_log_records() {
+        let events = format_events(DatadogFormat::new(true), || {
+            let logger = tracing_log::LogTracer::new();
+            log::Log::log(
+                &logger,
+                &log::Record::builder()
+                    .level(log::Level::Warn)
+                    .target("hyper::proto")
+                    .module_path_static(Some("hyper::proto"))
+                    .file_static(Some("src/proto.rs"))
+                    .line(Some(12))
+                    .args(format_args!("connection closed"))
+                    .build(),
+            );
+        });
+
+        let event: serde_json::Value =
+            serde_json::from_str(&events[0]).unwrap();
+        assert_eq!(event["target"], "hyper::proto");
+        assert_eq!(event["file"], "src/proto.rs");
+        assert_eq!(event["line"], 12);
+        assert_eq!(event["message"], "connection closed");
+        assert!(event.get("log.target").is_none());
     }
 }
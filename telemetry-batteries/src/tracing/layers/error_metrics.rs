@@ -0,0 +1,34 @@
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::tracing::panic::PANIC_TARGET;
+
+/// Counts error events as `logs.errors{target=...}` through the installed
+/// metrics recorder, so error spikes can be alerted on even when log
+/// indexing is sampled.
+///
+/// Panics logged by the panic hook are left out, they are counted as
+/// `process.panics`.
+pub fn error_metrics_layer<S>() -> impl Layer<S>
+where
+    S: Subscriber,
+{
+    ErrorMetricsLayer
+}
+
+struct ErrorMetricsLayer;
+
+impl<S> Layer<S> for ErrorMetricsLayer
+where
+    S: Subscriber,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+
+        if *meta.level() == Level::ERROR && meta.target() != PANIC_TARGET {
+            metrics::counter!("logs.errors", "target" => meta.target())
+                .increment(1);
+        }
+    }
+}
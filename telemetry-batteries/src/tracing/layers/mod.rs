@@ -1,29 +1,65 @@
+#[cfg(not(target_arch = "wasm32"))]
 use std::io::Write;
 
-use tokio::sync::OnceCell;
 use tracing::Subscriber;
-use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{fmt, registry::LookupSpan, Layer};
 
+pub mod compact;
+#[cfg(target_arch = "wasm32")]
+pub mod console;
 pub mod datadog;
+pub mod error_metrics;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod non_blocking;
+pub mod sampling;
 pub mod stdout;
 
 pub fn stdout_layer<S>() -> impl Layer<S>
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
 {
-    fmt::layer().with_target(false).with_level(true)
+    fmt::layer()
+        .with_ansi(stdout::ColorMode::Auto.enabled())
+        .with_target(false)
+        .with_level(true)
 }
 
-static WORKER_GUARD: OnceCell<WorkerGuard> = OnceCell::const_new();
-
+#[cfg(not(target_arch = "wasm32"))]
 pub fn non_blocking_writer_layer<S, W>(writer: W) -> impl Layer<S>
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
     W: Write + Send + Sync + 'static,
 {
-    let (non_blocking, guard) = tracing_appender::non_blocking(writer);
-    WORKER_GUARD.set(guard).expect("Could not set worker guard");
+    let non_blocking = non_blocking::non_blocking(writer);
+
+    tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(non_blocking)
+}
+
+/// Like [`non_blocking_writer_layer`], also writing the given span lifecycle
+/// events. Without `timing`, neither timestamps nor span durations are
+/// written.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn non_blocking_writer_layer_with_span_events<S, W>(
+    writer: W,
+    span_events: stdout::SpanEvents,
+    timing: bool,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: Write + Send + Sync + 'static,
+{
+    let non_blocking = non_blocking::non_blocking(writer);
+
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(non_blocking)
+        .with_span_events(span_events.fmt_span());
 
-    tracing_subscriber::fmt::layer().with_writer(non_blocking)
+    if timing {
+        layer.boxed()
+    } else {
+        layer.without_time().boxed()
+    }
 }
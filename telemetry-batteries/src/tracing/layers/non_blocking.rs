@@ -0,0 +1,208 @@
+//! The non-blocking file writer, with the bookkeeping needed to wait for the
+//! lines handed to it to be written, which `tracing_appender` only does when
+//! its guard is dropped.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::Duration;
+
+use tracing_appender::non_blocking::{ErrorCounter, NonBlocking, WorkerGuard};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Taken on shutdown, dropping the guard makes the worker write what is left
+/// and exit.
+static FILE_WRITER: Mutex<Option<FileWriter>> = Mutex::new(None);
+
+struct FileWriter {
+    _guard: WorkerGuard,
+    progress: Arc<Progress>,
+    dropped: ErrorCounter,
+}
+
+/// Lines sent to the worker and lines it has written and flushed.
+#[derive(Default)]
+struct Progress {
+    sent: AtomicU64,
+    flushed: Mutex<u64>,
+    flushed_changed: Condvar,
+}
+
+/// Starts the worker writing to `writer`, panics if one is already running.
+pub(crate) fn non_blocking<W>(writer: W) -> CountingMakeWriter
+where
+    W: Write + Send + 'static,
+{
+    let progress = Arc::new(Progress::default());
+    let (non_blocking, guard) =
+        tracing_appender::non_blocking(ProgressWriter {
+            inner: writer,
+            written: 0,
+            progress: Arc::clone(&progress),
+        });
+
+    let mut file_writer =
+        FILE_WRITER.lock().unwrap_or_else(PoisonError::into_inner);
+    assert!(file_writer.is_none(), "Could not set worker guard");
+    *file_writer = Some(FileWriter {
+        _guard: guard,
+        progress: Arc::clone(&progress),
+        dropped: non_blocking.error_counter(),
+    });
+
+    CountingMakeWriter {
+        inner: non_blocking,
+        progress,
+    }
+}
+
+/// Waits at most `timeout` for the lines sent so far to be written and
+/// flushed. Returns `true` if there is no file writer.
+pub(crate) fn flush_file_writer(timeout: Duration) -> bool {
+    let progress = {
+        let file_writer =
+            FILE_WRITER.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(file_writer) = file_writer.as_ref() else {
+            return true;
+        };
+
+        // Lines dropped because the channel was full are counted as sent.
+        let dropped = file_writer.dropped.dropped_lines() as u64;
+        let sent = file_writer.progress.sent.load(Ordering::Acquire);
+        (
+            Arc::clone(&file_writer.progress),
+            sent.saturating_sub(dropped),
+        )
+    };
+    let (progress, target) = progress;
+
+    let flushed = progress
+        .flushed
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let (_flushed, result) = progress
+        .flushed_changed
+        .wait_timeout_while(flushed, timeout, |flushed| *flushed < target)
+        .unwrap_or_else(PoisonError::into_inner);
+
+    !result.timed_out()
+}
+
+/// Stops the worker once it has written the pending lines, waiting up to a
+/// second for it.
+pub(crate) fn shut_down_file_writer() {
+    let file_writer = FILE_WRITER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+
+    drop(file_writer);
+}
+
+/// Counts the lines sent to the worker, one per event.
+#[derive(Clone)]
+pub struct CountingMakeWriter {
+    inner: NonBlocking,
+    progress: Arc<Progress>,
+}
+
+impl<'a> MakeWriter<'a> for CountingMakeWriter {
+    type Writer = CountingWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        CountingWriter {
+            inner: self.inner.clone(),
+            progress: &self.progress,
+        }
+    }
+}
+
+pub struct CountingWriter<'a> {
+    inner: NonBlocking,
+    progress: &'a Progress,
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // `NonBlocking` sends the whole buffer as a single line.
+        let written = self.inner.write(buf)?;
+        self.progress.sent.fetch_add(1, Ordering::Release);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Runs on the worker, which writes each line with `write_all` and flushes
+/// after every batch.
+struct ProgressWriter<W> {
+    inner: W,
+    written: u64,
+    progress: Arc<Progress>,
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        // Failed lines are counted too, they won't be retried.
+        self.written += 1;
+        self.inner.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+
+        *self
+            .progress
+            .flushed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = self.written;
+        self.progress.flushed_changed.notify_all();
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shares what the worker wrote with the test.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_file_writer() {
+        let buffer = SharedBuffer::default();
+        let make_writer = non_blocking(buffer.clone());
+
+        for line in ["first\n", "second\n"] {
+            make_writer
+                .make_writer()
+                .write_all(line.as_bytes())
+                .unwrap();
+        }
+
+        assert!(flush_file_writer(Duration::from_secs(5)));
+        assert_eq!(buffer.0.lock().unwrap().as_slice(), b"first\nsecond\n");
+
+        shut_down_file_writer();
+        assert!(flush_file_writer(Duration::ZERO));
+    }
+}
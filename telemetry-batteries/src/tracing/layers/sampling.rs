@@ -0,0 +1,169 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+const WINDOW_MILLIS: u64 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LogSamplingConfig {
+    // Number of events below WARN per second kept before sampling kicks in.
+    pub max_events_per_second: u64,
+}
+
+impl Default for LogSamplingConfig {
+    fn default() -> Self {
+        Self {
+            max_events_per_second: 1000,
+        }
+    }
+}
+
+/// Samples events below WARN once their rate exceeds
+/// `max_events_per_second`, protecting the writers and the agent during
+/// incident storms. WARN and ERROR events are always kept.
+///
+/// The sampling rate is recomputed every second from the rate of the
+/// previous second, so that on average `max_events_per_second` events are
+/// kept. It is reported as the `logs.sampling.rate` gauge, dropped events are
+/// counted as `logs.sampling.dropped`.
+///
+/// The layer drops events for the whole subscriber, so it should be added
+/// before the format layers.
+pub fn log_sampling_layer<S>(config: LogSamplingConfig) -> impl Layer<S>
+where
+    S: Subscriber,
+{
+    LogSamplingLayer::new(config)
+}
+
+struct LogSamplingLayer {
+    max_events_per_second: u64,
+    started: Instant,
+    // Start of the current window, in milliseconds since `started`.
+    window_start: AtomicU64,
+    window_events: AtomicU64,
+    // Fraction of events kept, as `f64` bits.
+    rate: AtomicU64,
+}
+
+impl LogSamplingLayer {
+    fn new(config: LogSamplingConfig) -> Self {
+        Self {
+            max_events_per_second: config.max_events_per_second,
+            started: Instant::now(),
+            window_start: AtomicU64::new(0),
+            window_events: AtomicU64::new(0),
+            rate: AtomicU64::new(1.0f64.to_bits()),
+        }
+    }
+
+    fn current_rate(&self) -> f64 {
+        let now = self.started.elapsed().as_millis() as u64;
+        let window_start = self.window_start.load(Ordering::Relaxed);
+
+        let rolled_over = now.saturating_sub(window_start) >= WINDOW_MILLIS
+            && self
+                .window_start
+                .compare_exchange(
+                    window_start,
+                    now,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok();
+
+        if !rolled_over {
+            return f64::from_bits(self.rate.load(Ordering::Relaxed));
+        }
+
+        let events = self.window_events.swap(0, Ordering::Relaxed);
+        let elapsed_seconds = (now - window_start) as f64 / 1000.0;
+        let events_per_second = events as f64 / elapsed_seconds;
+
+        let rate = if events_per_second > self.max_events_per_second as f64 {
+            self.max_events_per_second as f64 / events_per_second
+        } else {
+            1.0
+        };
+
+        // Events emitted from within the subscriber are discarded by
+        // `tracing`, so the rate is only reported as a metric.
+        self.rate.store(rate.to_bits(), Ordering::Relaxed);
+        metrics::gauge!("logs.sampling.rate").set(rate);
+
+        rate
+    }
+}
+
+impl<S> Layer<S> for LogSamplingLayer
+where
+    S: Subscriber,
+{
+    fn event_enabled(&self, event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        if *event.metadata().level() <= Level::WARN {
+            return true;
+        }
+
+        self.window_events.fetch_add(1, Ordering::Relaxed);
+
+        let rate = self.current_rate();
+        if rate >= 1.0 || rand::thread_rng().gen_bool(rate) {
+            return true;
+        }
+
+        metrics::counter!("logs.sampling.dropped").increment(1);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    struct CountingLayer(Arc<AtomicUsize>, Level);
+
+    impl<S: Subscriber> Layer<S> for CountingLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            if *event.metadata().level() == self.1 {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_samples_info_but_not_warn() {
+        let infos = Arc::new(AtomicUsize::new(0));
+        let warns = Arc::new(AtomicUsize::new(0));
+
+        let mut sampling = LogSamplingLayer::new(LogSamplingConfig {
+            max_events_per_second: 10,
+        });
+        // Pretend the first second already saw 1000 events.
+        sampling.window_events.store(1000, Ordering::Relaxed);
+        sampling.started -= std::time::Duration::from_millis(WINDOW_MILLIS);
+
+        let subscriber = tracing_subscriber::registry()
+            .with(sampling)
+            .with(CountingLayer(Arc::clone(&infos), Level::INFO))
+            .with(CountingLayer(Arc::clone(&warns), Level::WARN));
+
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..1000 {
+                tracing::info!("noisy");
+                tracing::warn!("important");
+            }
+        });
+
+        // The rate is 10 / 1000, so about 10 of the INFO events are kept.
+        assert!(infos.load(Ordering::Relaxed) < 100);
+        assert_eq!(warns.load(Ordering::Relaxed), 1000);
+    }
+}
@@ -1,15 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::IsTerminal;
 use tracing::Subscriber;
+use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::{registry::LookupSpan, EnvFilter, Layer};
 
+/// Whether the stdout layers write ANSI colors.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Colors when stdout is a terminal, unless `NO_COLOR` is set.
+    /// `CLICOLOR_FORCE` forces colors when stdout is not a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Returns whether output written to stdout should be colored.
+    pub fn enabled(self) -> bool {
+        self.resolve(
+            env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()),
+            env::var_os("CLICOLOR_FORCE")
+                .is_some_and(|value| !value.is_empty() && value != "0"),
+            std::io::stdout().is_terminal(),
+        )
+    }
+
+    fn resolve(
+        self,
+        no_color: bool,
+        force_color: bool,
+        terminal: bool,
+    ) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => !no_color && (force_color || terminal),
+        }
+    }
+}
+
+/// Which span lifecycle events the fmt layers write.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SpanEvents {
+    #[default]
+    None,
+    /// An event when a span is created.
+    New,
+    /// An event when a span is closed, with its busy and idle durations
+    /// unless timing is disabled.
+    Close,
+    /// Events for every transition of a span: new, enter, exit and close.
+    Full,
+}
+
+impl SpanEvents {
+    pub fn fmt_span(self) -> FmtSpan {
+        match self {
+            Self::None => FmtSpan::NONE,
+            Self::New => FmtSpan::NEW,
+            Self::Close => FmtSpan::CLOSE,
+            Self::Full => FmtSpan::FULL,
+        }
+    }
+}
+
 pub fn stdout_layer<S>() -> impl Layer<S>
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
 {
-    tracing_subscriber::fmt::layer()
+    stdout_layer_with_color(ColorMode::Auto)
+}
+
+pub fn stdout_layer_with_color<S>(color: ColorMode) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    stdout_layer_with_span_events(color, SpanEvents::None, true)
+}
+
+/// Like [`stdout_layer_with_color`], also writing the given span lifecycle
+/// events. Without `timing`, neither timestamps nor span durations are
+/// written.
+pub fn stdout_layer_with_span_events<S>(
+    color: ColorMode,
+    span_events: SpanEvents,
+    timing: bool,
+) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let layer = tracing_subscriber::fmt::layer()
         .with_writer(std::io::stdout)
+        .with_ansi(color.enabled())
         .pretty()
         .with_target(false)
         .with_line_number(true)
         .with_file(true)
-        .with_filter(EnvFilter::from_default_env())
+        .with_span_events(span_events.fmt_span());
+
+    let layer = if timing {
+        layer.boxed()
+    } else {
+        layer.without_time().boxed()
+    };
+
+    layer.with_filter(EnvFilter::from_default_env())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_mode() {
+        assert!(ColorMode::Auto.resolve(false, false, true));
+        assert!(!ColorMode::Auto.resolve(false, false, false));
+        assert!(!ColorMode::Auto.resolve(true, false, true));
+        assert!(ColorMode::Auto.resolve(false, true, false));
+        assert!(!ColorMode::Auto.resolve(true, true, false));
+        assert!(ColorMode::Always.resolve(true, false, false));
+        assert!(!ColorMode::Never.resolve(false, true, true));
+    }
 }
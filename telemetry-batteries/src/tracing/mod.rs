@@ -1,41 +1,293 @@
+#[cfg(feature = "datadog")]
 pub mod datadog;
+#[cfg(feature = "datadog")]
 pub mod id_generator;
 pub mod layers;
+pub mod panic;
+#[cfg(feature = "datadog")]
+pub mod processor;
 pub mod stdout;
+pub mod timestamp;
 
+#[cfg(feature = "datadog")]
 use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceId};
+#[cfg(feature = "datadog")]
 use opentelemetry::Context;
+#[cfg(feature = "datadog")]
+use opentelemetry_sdk::trace::TracerProvider;
 
 use std::path::PathBuf;
+#[cfg(feature = "datadog")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "datadog")]
+use std::sync::{mpsc, OnceLock};
+use std::time::{Duration, Instant};
+#[cfg(feature = "datadog")]
+use std::{env, thread};
 use std::{fs, io};
 use tracing::Subscriber;
+#[cfg(feature = "datadog")]
 use tracing_opentelemetry::OpenTelemetrySpanExt;
+#[cfg(feature = "datadog")]
 use tracing_opentelemetry::OtelData;
 use tracing_subscriber::fmt::{FmtContext, FormatFields};
-use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::registry::LookupSpan;
+#[cfg(feature = "datadog")]
+use tracing_subscriber::registry::SpanRef;
 pub use tracing_subscriber::Registry;
 
+#[cfg(not(target_arch = "wasm32"))]
+use self::layers::non_blocking::{flush_file_writer, shut_down_file_writer};
+
+/// Tracer provider installed by the datadog layer, kept around so that
+/// spans can be flushed on demand.
+#[cfg(feature = "datadog")]
+pub(crate) static TRACER_PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+/// Disables span export at startup when set to `false` or `0`.
+#[cfg(feature = "datadog")]
+pub const TELEMETRY_ENABLED_ENV: &str = "TELEMETRY_ENABLED";
+
+/// Whether spans reach the otel layer, see
+/// [`TracingShutdownHandle::set_tracing_enabled`].
+#[cfg(feature = "datadog")]
+static TRACING_ENABLED: AtomicBool = AtomicBool::new(true);
+
+#[cfg(feature = "datadog")]
+pub(crate) fn tracing_enabled() -> bool {
+    TRACING_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Applies [`TELEMETRY_ENABLED_ENV`], called when the otel layer is built.
+#[cfg(feature = "datadog")]
+pub(crate) fn init_tracing_enabled_from_env() {
+    let disabled = env::var(TELEMETRY_ENABLED_ENV)
+        .is_ok_and(|value| value == "0" || value.eq_ignore_ascii_case("false"));
+
+    if disabled {
+        TRACING_ENABLED.store(false, Ordering::Relaxed);
+    }
+}
+
 /// `TracingShutdownHandle` ensures the global tracing provider
 /// is gracefully shut down when the handle is dropped, preventing loss
 /// of any remaining traces not yet exported.
+///
+/// The non-blocking file writer is stopped once the tracing provider has been
+/// shut down, after writing the pending lines. Guards attached with
+/// [`TracingShutdownHandle::attach`] are dropped last, in the order they were
+/// attached.
 #[must_use]
-pub struct TracingShutdownHandle;
+#[derive(Default)]
+pub struct TracingShutdownHandle {
+    #[cfg(feature = "datadog")]
+    shutdown_timeout: Option<Duration>,
+    is_shut_down: bool,
+    guards: Vec<Box<dyn Send>>,
+}
+
+impl TracingShutdownHandle {
+    /// Bounds how long dropping the handle waits for the tracer provider to
+    /// shut down. Without a timeout the drop blocks until the batch processor
+    /// has exported its buffered spans, or given up on them after its own
+    /// export timeout.
+    #[cfg(feature = "datadog")]
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Ties the lifetime of `guard` (e.g. a `WorkerGuard` or another
+    /// exporter's shutdown handle) to this handle.
+    pub fn attach<G>(&mut self, guard: G)
+    where
+        G: Send + 'static,
+    {
+        self.guards.push(Box::new(guard));
+    }
+
+    /// Forces the span batch processor to export everything it has buffered,
+    /// waits for the non-blocking file writer to write the lines sent so far
+    /// and, with the `metrics-exporters` feature, pushes the metrics to the
+    /// Prometheus push gateway and sends the buffered StatsD metrics.
+    ///
+    /// `timeout` bounds the whole flush. Returns `true` if everything was
+    /// flushed successfully within it.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let remaining = || deadline.saturating_duration_since(Instant::now());
+
+        let spans = flush_tracer_provider(remaining());
+        let logs = flush_file_writer(remaining());
+
+        #[cfg(feature = "metrics-exporters")]
+        {
+            use crate::metrics::{
+                prometheus::PrometheusBattery, statsd::StatsdBattery,
+            };
+
+            let prometheus = PrometheusBattery::flush(remaining());
+            let statsd = StatsdBattery::flush(remaining()).is_ok();
+
+            spans && logs && prometheus && statsd
+        }
+
+        #[cfg(not(feature = "metrics-exporters"))]
+        {
+            spans && logs
+        }
+    }
+
+    /// Starts or stops handing spans to the otel layer, e.g. to stop
+    /// exporting to Datadog without a redeploy. Spans already open keep
+    /// being exported until they close.
+    ///
+    /// While disabled, logs no longer carry `dd.trace_id` and `dd.span_id`
+    /// and no trace context is propagated. Log output is unaffected
+    /// otherwise.
+    #[cfg(feature = "datadog")]
+    pub fn set_tracing_enabled(&self, enabled: bool) {
+        let was_enabled = TRACING_ENABLED.swap(enabled, Ordering::Relaxed);
+        if was_enabled != enabled {
+            tracing::warn!(enabled, "Span export toggled");
+        }
+    }
+
+    /// Returns whether spans are handed to the otel layer.
+    #[cfg(feature = "datadog")]
+    pub fn is_tracing_enabled(&self) -> bool {
+        tracing_enabled()
+    }
+
+    /// Shuts down the tracer provider and the non-blocking file writer from
+    /// async code.
+    ///
+    /// The blocking shutdown runs on tokio's blocking pool, so unlike
+    /// dropping the handle it can't stall or deadlock a runtime worker. Drop
+    /// remains a best-effort fallback when this isn't called.
+    pub async fn shutdown(mut self) {
+        self.is_shut_down = true;
+
+        #[cfg(feature = "datadog")]
+        let timeout = self.shutdown_timeout;
+        let _ = tokio::task::spawn_blocking(move || {
+            #[cfg(feature = "datadog")]
+            shutdown_tracer_provider(timeout);
+
+            shut_down_file_writer();
+        })
+        .await;
+    }
+}
 
 impl Drop for TracingShutdownHandle {
     fn drop(&mut self) {
-        tracing::warn!("Shutting down tracing provider");
-        opentelemetry::global::shutdown_tracer_provider();
+        if !self.is_shut_down {
+            #[cfg(feature = "datadog")]
+            shutdown_tracer_provider(self.shutdown_timeout);
+
+            shut_down_file_writer();
+        }
+    }
+}
+
+/// Shuts down the tracer provider installed by the datadog layer, waiting at
+/// most `timeout`.
+#[cfg(feature = "datadog")]
+fn shutdown_tracer_provider(timeout: Option<Duration>) {
+    let Some(provider) = TRACER_PROVIDER.get() else {
+        return;
+    };
+
+    tracing::warn!("Shutting down tracing provider");
+
+    let Some(timeout) = timeout else {
+        let _ = provider.shutdown();
+        log_span_counts(None);
+        return;
+    };
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = provider.shutdown();
+        let _ = tx.send(());
+    });
+
+    let timed_out = rx.recv_timeout(timeout).is_err();
+    log_span_counts(timed_out.then_some(timeout));
+}
+
+/// Logs how many spans were exported and dropped, once the provider is shut
+/// down or, if `timed_out` is set, gave up waiting for it.
+#[cfg(feature = "datadog")]
+fn log_span_counts(timed_out: Option<Duration>) {
+    let (exported, dropped) = processor::span_counts();
+
+    match timed_out {
+        None => {
+            tracing::info!(exported, dropped, "Tracing provider shut down");
+        }
+        Some(timeout) => tracing::warn!(
+            ?timeout,
+            exported,
+            dropped,
+            "Tracing provider did not shut down in time, pending spans are counted as dropped"
+        ),
     }
 }
 
-pub fn trace_from_headers(headers: &http::HeaderMap) {
-    tracing::Span::current().set_parent(
+/// Flushes the tracer provider installed by the datadog layer, waiting at
+/// most `timeout`. Returns `true` if there is nothing to flush.
+#[cfg(feature = "datadog")]
+pub(crate) fn flush_tracer_provider(timeout: Duration) -> bool {
+    let Some(provider) = TRACER_PROVIDER.get().cloned() else {
+        return true;
+    };
+
+    // `force_flush` blocks until the batch processor answers, so run it
+    // off the current thread to avoid stalling an async runtime worker.
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let flushed = provider
+            .force_flush()
+            .into_iter()
+            .all(|result| result.is_ok());
+        let _ = tx.send(flushed);
+    });
+
+    rx.recv_timeout(timeout).unwrap_or(false)
+}
+
+#[cfg(not(feature = "datadog"))]
+pub(crate) fn flush_tracer_provider(_timeout: Duration) -> bool {
+    true
+}
+
+#[cfg(target_arch = "wasm32")]
+fn flush_file_writer(_timeout: Duration) -> bool {
+    true
+}
+
+#[cfg(target_arch = "wasm32")]
+fn shut_down_file_writer() {}
+
+/// Sets the context propagated in `headers` as the parent of the current
+/// span. Returns whether the headers held a valid remote span context, if
+/// not the span stays the root of a new trace.
+#[cfg(feature = "datadog")]
+pub fn trace_from_headers(headers: &http::HeaderMap) -> bool {
+    let context =
         opentelemetry::global::get_text_map_propagator(|propagator| {
             propagator.extract(&opentelemetry_http::HeaderExtractor(headers))
-        }),
-    );
+        });
+    let span_context = context.span().span_context().clone();
+
+    tracing::Span::current().set_parent(context);
+
+    span_context.is_valid() && span_context.is_remote()
 }
 
+#[cfg(feature = "datadog")]
 pub fn trace_to_headers(headers: &mut http::HeaderMap) {
     opentelemetry::global::get_text_map_propagator(|propagator| {
         propagator.inject_context(
@@ -47,6 +299,7 @@ pub fn trace_to_headers(headers: &mut http::HeaderMap) {
 
 /// Finds Otel trace id by going up the span stack until we find a span
 /// with a trace id.
+#[cfg(feature = "datadog")]
 pub fn opentelemetry_trace_id<S, N>(ctx: &FmtContext<'_, S, N>) -> Option<u128>
 where
     S: Subscriber + for<'lookup> LookupSpan<'lookup>,
@@ -74,6 +327,16 @@ where
     }
 }
 
+/// Always `None` without the `datadog` feature.
+#[cfg(not(feature = "datadog"))]
+pub fn opentelemetry_trace_id<S, N>(_ctx: &FmtContext<'_, S, N>) -> Option<u128>
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    None
+}
+
 /// Finds Otel span id
 ///
 /// BUG: The otel object is not available for span end events. This is
@@ -81,6 +344,7 @@ where
 /// extension before we get here.
 ///
 /// Fallbacks on tracing span id
+#[cfg(feature = "datadog")]
 pub fn opentelemetry_span_id<S, N>(ctx: &FmtContext<'_, S, N>) -> Option<u64>
 where
     S: Subscriber + for<'lookup> LookupSpan<'lookup>,
@@ -104,13 +368,25 @@ where
     }
 }
 
+/// Always `None` without the `datadog` feature.
+#[cfg(not(feature = "datadog"))]
+pub fn opentelemetry_span_id<S, N>(_ctx: &FmtContext<'_, S, N>) -> Option<u64>
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    None
+}
+
 /// Sets the current span's parent to the specified context
+#[cfg(feature = "datadog")]
 pub fn trace_from_ctx(ctx: SpanContext) {
     let parent_ctx = Context::new().with_remote_span_context(ctx);
     tracing::Span::current().set_parent(parent_ctx);
 }
 
 // Extracts the trace id and span id from the current span
+#[cfg(feature = "datadog")]
 pub fn extract_span_ids() -> (TraceId, SpanId) {
     let current_span = tracing::Span::current();
     let current_context = current_span.context();
@@ -123,6 +399,7 @@ pub fn extract_span_ids() -> (TraceId, SpanId) {
     (trace_id, span_id)
 }
 
+#[cfg(feature = "datadog")]
 fn span_from_ctx<'a, S, N>(
     ctx: &'a FmtContext<'a, S, N>,
 ) -> Option<SpanRef<'a, S>>
@@ -184,3 +461,51 @@ pub fn get_log_directory() -> Result<PathBuf, io::Error> {
 
     Ok(log_dir)
 }
+
+#[cfg(all(test, feature = "datadog"))]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_trace_from_headers() {
+        opentelemetry::global::set_text_map_propagator(
+            TraceContextPropagator::new(),
+        );
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_opentelemetry::OpenTelemetryLayer::new(
+                provider.tracer("test"),
+            ),
+        );
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+                .parse()
+                .unwrap(),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                assert!(trace_from_headers(&headers));
+                assert_eq!(
+                    tracing::Span::current()
+                        .context()
+                        .span()
+                        .span_context()
+                        .trace_id(),
+                    TraceId::from_hex("0af7651916cd43dd8448eb211c80319c")
+                        .unwrap()
+                );
+            });
+
+            tracing::info_span!("request").in_scope(|| {
+                assert!(!trace_from_headers(&http::HeaderMap::new()));
+            });
+        });
+    }
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io, panic, process, thread};
+
+use crate::tracing::timestamp::UtcTime;
+use crate::tracing::{flush_tracer_provider, get_log_directory};
+
+/// How long the panic hook waits for buffered spans to be exported.
+pub const PANIC_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Target of the panic events, which [`error_metrics_layer`] doesn't count
+/// since panics are already counted as `process.panics`.
+///
+/// [`error_metrics_layer`]: crate::tracing::layers::error_metrics::error_metrics_layer
+pub(crate) const PANIC_TARGET: &str = "panic";
+
+const CRASH_FILE_PREFIX: &str = "panic-";
+const CRASH_FILE_SUFFIX: &str = ".json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CrashReportConfig {
+    // Directory crash reports are written to. Defaults to `crashes` in the
+    // log directory.
+    pub directory: Option<PathBuf>,
+
+    // Number of crash reports kept, the oldest ones are removed when a new
+    // report is written.
+    pub max_files: usize,
+}
+
+impl Default for CrashReportConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            max_files: 10,
+        }
+    }
+}
+
+/// Installs a panic hook that reports panics through `tracing`.
+///
+/// The panic is counted as `process.panics`, logged as an error event carrying
+/// the message, location and backtrace (plus the trace ids added by the
+/// installed format layers), the tracer provider is flushed so the event's
+/// trace reaches the agent, and then the previously installed hook runs as
+/// usual.
+///
+/// The event has the `panic` target and isn't counted in `logs.errors`.
+pub fn install_panic_hook() {
+    set_hook(None);
+}
+
+/// Installs the panic hook of [`install_panic_hook`], additionally writing
+/// every panic as a JSON crash report to a file.
+///
+/// The report is written before the panic is logged, so it survives even if
+/// stdout or the log shipper are lost with the process.
+///
+/// # Errors
+/// Returns an error if the crash report directory can't be created.
+pub fn install_panic_hook_with_crash_reports(
+    config: CrashReportConfig,
+) -> io::Result<()> {
+    let directory = match config.directory {
+        Some(directory) => directory,
+        None => get_log_directory()?.join("crashes"),
+    };
+    fs::create_dir_all(&directory)?;
+
+    set_hook(Some(CrashReports {
+        directory,
+        max_files: config.max_files,
+    }));
+
+    Ok(())
+}
+
+struct CrashReports {
+    directory: PathBuf,
+    max_files: usize,
+}
+
+fn set_hook(crash_reports: Option<CrashReports>) {
+    let previous_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        let location = info.location().map(ToString::to_string);
+        let backtrace = Backtrace::force_capture();
+
+        if let Some(crash_reports) = &crash_reports {
+            let now = UtcTime::now();
+            let report = serde_json::json!({
+                "timestamp": now.to_rfc3339(now.exact_digits()),
+                "pid": process::id(),
+                "thread": thread::current().name(),
+                "message": message,
+                "location": location,
+                "backtrace": backtrace.to_string(),
+            });
+
+            if let Err(err) = write_crash_report(
+                &crash_reports.directory,
+                crash_reports.max_files,
+                &report,
+            ) {
+                eprintln!("failed to write crash report: {err}");
+            }
+        }
+
+        metrics::counter!("process.panics").increment(1);
+
+        tracing::error!(
+            target: PANIC_TARGET,
+            {
+                panic.message = message,
+                panic.location = location.as_deref(),
+                panic.backtrace = %backtrace,
+            },
+            "panic"
+        );
+
+        flush_tracer_provider(PANIC_FLUSH_TIMEOUT);
+
+        previous_hook(info);
+    }));
+}
+
+/// Writes `report` to a new file in `directory`, removing the oldest reports
+/// so at most `max_files` are kept.
+fn write_crash_report(
+    directory: &Path,
+    max_files: usize,
+    report: &serde_json::Value,
+) -> io::Result<PathBuf> {
+    let mut existing: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name().and_then(|name| name.to_str()).is_some_and(
+                |name| {
+                    name.starts_with(CRASH_FILE_PREFIX)
+                        && name.ends_with(CRASH_FILE_SUFFIX)
+                },
+            )
+        })
+        .collect();
+
+    // File names start with a fixed width timestamp, so they sort by age.
+    existing.sort();
+
+    // A report that can't be removed, e.g. already removed by another
+    // process, mustn't prevent the new one from being written.
+    let excess = (existing.len() + 1).saturating_sub(max_files.max(1));
+    for path in existing.iter().take(excess) {
+        if let Err(err) = fs::remove_file(path) {
+            eprintln!(
+                "failed to remove crash report {}: {err}",
+                path.display()
+            );
+        }
+    }
+
+    let now = UtcTime::now();
+    let stem = format!(
+        "{CRASH_FILE_PREFIX}{:04}{:02}{:02}T{:02}{:02}{:02}.{:06}Z-{}",
+        now.year,
+        now.month,
+        now.day,
+        now.hour,
+        now.minute,
+        now.second,
+        now.nanos / 1_000,
+        process::id()
+    );
+    let (path, mut file) = create_new_file(directory, &stem)?;
+    file.write_all(&serde_json::to_vec(report)?)?;
+
+    Ok(path)
+}
+
+/// Creates `<stem>.json` in `directory`, or `<stem>-<n>.json` with the first
+/// free `n` when another thread panicking at the same time took the name.
+fn create_new_file(
+    directory: &Path,
+    stem: &str,
+) -> io::Result<(PathBuf, File)> {
+    let mut path = directory.join(format!("{stem}{CRASH_FILE_SUFFIX}"));
+    for n in 1.. {
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                path = directory.join(format!("{stem}-{n}{CRASH_FILE_SUFFIX}"));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    unreachable!("ran out of crash report file names")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_crash_report_rotates() {
+        let directory = std::env::temp_dir()
+            .join(format!("telemetry-batteries-crashes-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let report = serde_json::json!({ "message": "boom" });
+        let mut written = Vec::new();
+        for _ in 0..3 {
+            written.push(write_crash_report(&directory, 2, &report).unwrap());
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        assert!(!written[0].exists());
+        assert!(written[1].exists());
+        assert!(written[2].exists());
+
+        let contents = fs::read_to_string(&written[2]).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&contents).unwrap(),
+            report
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_create_new_file_doesnt_overwrite() {
+        let directory = std::env::temp_dir()
+            .join(format!("telemetry-batteries-crash-names-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let (first, _) = create_new_file(&directory, "panic-1").unwrap();
+        let (second, _) = create_new_file(&directory, "panic-1").unwrap();
+
+        assert_eq!(first, directory.join("panic-1.json"));
+        assert_eq!(second, directory.join("panic-1-1.json"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+//! Span processors rewriting spans before they are exported, wrapping the
+//! processor that hands them to the exporter, and the counts of spans ended
+//! and exported logged on shutdown.
+
+use std::borrow::Cow;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use opentelemetry::trace::TraceResult;
+use opentelemetry::Context;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::trace::{Span, SpanProcessor};
+use opentelemetry_sdk::Resource;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Comma separated attribute keys removed from every span by the Datadog
+/// pipeline, see [`AttributeFilter::Deny`].
+pub const SPAN_ATTRIBUTES_DENY_ENV: &str = "TELEMETRY_SPAN_ATTRIBUTES_DENY";
+
+/// Comma separated attribute keys kept on spans by the Datadog pipeline, see
+/// [`AttributeFilter::Allow`]. Takes precedence over
+/// [`SPAN_ATTRIBUTES_DENY_ENV`].
+pub const SPAN_ATTRIBUTES_ALLOW_ENV: &str = "TELEMETRY_SPAN_ATTRIBUTES_ALLOW";
+
+/// Span name rewrite rules applied by the Datadog pipeline, as a JSON array
+/// of [`SpanNameRule`]s.
+pub const SPAN_NAME_RULES_ENV: &str = "TELEMETRY_SPAN_NAME_RULES";
+
+/// Which span attributes are exported.
+///
+/// Keys ending with `*` match every key starting with what precedes it, e.g.
+/// `http.request.header.*`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeFilter {
+    #[default]
+    All,
+
+    // Removes the attributes matching any of the keys.
+    Deny(Vec<String>),
+
+    // Removes the attributes matching none of the keys.
+    Allow(Vec<String>),
+}
+
+impl AttributeFilter {
+    /// Reads [`SPAN_ATTRIBUTES_ALLOW_ENV`] and [`SPAN_ATTRIBUTES_DENY_ENV`].
+    pub fn from_env() -> Self {
+        let keys = |name| {
+            let keys = env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+
+            (!keys.is_empty()).then_some(keys)
+        };
+
+        if let Some(keys) = keys(SPAN_ATTRIBUTES_ALLOW_ENV) {
+            Self::Allow(keys)
+        } else if let Some(keys) = keys(SPAN_ATTRIBUTES_DENY_ENV) {
+            Self::Deny(keys)
+        } else {
+            Self::All
+        }
+    }
+
+    fn keeps(&self, key: &str) -> bool {
+        let matches = |keys: &[String]| {
+            keys.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => key.starts_with(prefix),
+                None => key == pattern,
+            })
+        };
+
+        match self {
+            Self::All => true,
+            Self::Deny(keys) => !matches(keys),
+            Self::Allow(keys) => matches(keys),
+        }
+    }
+}
+
+/// Removes the span attributes rejected by `filter` before passing ended
+/// spans to `inner`, so that they never leave the process.
+#[derive(Debug)]
+pub struct AttributeFilterProcessor<P> {
+    inner: P,
+    filter: AttributeFilter,
+}
+
+impl<P> AttributeFilterProcessor<P> {
+    pub fn new(inner: P, filter: AttributeFilter) -> Self {
+        Self { inner, filter }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for AttributeFilterProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        if self.filter != AttributeFilter::All {
+            let before = span.attributes.len();
+            span.attributes
+                .retain(|attribute| self.filter.keeps(attribute.key.as_str()));
+            span.dropped_attributes_count +=
+                (before - span.attributes.len()) as u32;
+        }
+
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Rewrites span names with [`SpanNameRule`]s, e.g. to collapse ids in
+/// paths when no route template is available, since Datadog computes its
+/// stats per span name.
+#[derive(Debug)]
+pub struct SpanNameProcessor<P> {
+    inner: P,
+    rules: Vec<(Regex, String)>,
+}
+
+/// Replaces every match of `pattern` in span names with `replacement`,
+/// which can refer to capture groups as `$1` or `$name`.
+///
+/// ```json
+/// { "pattern": "/[0-9]+(/|$)", "replacement": "/{id}$1" }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SpanNameRule {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+impl<P> SpanNameProcessor<P> {
+    /// Fails if a pattern is not a valid regex.
+    pub fn new(
+        inner: P,
+        rules: Vec<SpanNameRule>,
+    ) -> Result<Self, regex::Error> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| Ok((Regex::new(&rule.pattern)?, rule.replacement)))
+            .collect::<Result<_, regex::Error>>()?;
+
+        Ok(Self { inner, rules })
+    }
+
+    /// Reads the rules from [`SPAN_NAME_RULES_ENV`], ignoring the invalid
+    /// ones.
+    pub(crate) fn from_env(inner: P) -> Self {
+        let rules = env::var(SPAN_NAME_RULES_ENV)
+            .map(|rules| parse_rules(&rules))
+            .unwrap_or_default();
+
+        Self { inner, rules }
+    }
+
+    fn rename(&self, name: &str) -> Option<String> {
+        let mut renamed: Option<String> = None;
+        for (pattern, replacement) in &self.rules {
+            let current = renamed.as_deref().unwrap_or(name);
+            if let Cow::Owned(replaced) =
+                pattern.replace_all(current, replacement.as_str())
+            {
+                renamed = Some(replaced);
+            }
+        }
+
+        renamed
+    }
+}
+
+/// Parses a JSON array of [`SpanNameRule`]s, skipping the rules whose
+/// pattern is not a valid regex.
+///
+/// Problems are written to stderr, the subscriber that would log them isn't
+/// installed yet while the pipeline is built.
+fn parse_rules(rules: &str) -> Vec<(Regex, String)> {
+    if rules.trim().is_empty() {
+        return Vec::new();
+    }
+
+    let rules: Vec<SpanNameRule> = match serde_json::from_str(rules) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!(
+                "Ignoring {SPAN_NAME_RULES_ENV}, expected a JSON array of \
+                 rules: {e}"
+            );
+            return Vec::new();
+        }
+    };
+
+    rules
+        .into_iter()
+        .filter_map(|rule| match Regex::new(&rule.pattern) {
+            Ok(pattern) => Some((pattern, rule.replacement)),
+            Err(e) => {
+                eprintln!("Ignoring span name rule {:?}: {e}", rule.pattern);
+                None
+            }
+        })
+        .collect()
+}
+
+impl<P: SpanProcessor> SpanProcessor for SpanNameProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        if let Some(name) = self.rename(&span.name) {
+            span.name = name.into();
+        }
+
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Spans handed to the processors and spans the exporter sent successfully,
+/// since the start of the process.
+static SPANS_ENDED: AtomicU64 = AtomicU64::new(0);
+static SPANS_EXPORTED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns how many spans were exported and how many were dropped, i.e.
+/// ended without being exported, so far.
+pub(crate) fn span_counts() -> (u64, u64) {
+    let exported = SPANS_EXPORTED.load(Ordering::Relaxed);
+    let ended = SPANS_ENDED.load(Ordering::Relaxed);
+
+    (exported, ended.saturating_sub(exported))
+}
+
+/// Counts the spans ended, before `inner` gets a chance to drop them.
+#[derive(Debug)]
+pub(crate) struct CountingProcessor<P> {
+    inner: P,
+}
+
+impl<P> CountingProcessor<P> {
+    pub(crate) fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for CountingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        SPANS_ENDED.fetch_add(1, Ordering::Relaxed);
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Counts the spans `inner` exported successfully.
+#[derive(Debug)]
+pub(crate) struct CountingExporter<E> {
+    inner: E,
+}
+
+impl<E> CountingExporter<E> {
+    pub(crate) fn new(inner: E) -> Self {
+        Self { inner }
+    }
+}
+
+impl<E: SpanExporter> SpanExporter for CountingExporter<E> {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        let len = batch.len() as u64;
+        let export = self.inner.export(batch);
+
+        Box::pin(async move {
+            let result = export.await;
+            if result.is_ok() {
+                SPANS_EXPORTED.fetch_add(len, Ordering::Relaxed);
+            }
+
+            result
+        })
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown();
+    }
+
+    fn force_flush(
+        &mut self,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribute_filter() {
+        let deny = AttributeFilter::Deny(vec![
+            "db.statement".to_string(),
+            "http.request.header.*".to_string(),
+        ]);
+        assert!(!deny.keeps("db.statement"));
+        assert!(!deny.keeps("http.request.header.authorization"));
+        assert!(deny.keeps("db.system"));
+
+        let allow = AttributeFilter::Allow(vec!["http.*".to_string()]);
+        assert!(allow.keeps("http.route"));
+        assert!(!allow.keeps("user.email"));
+
+        assert!(AttributeFilter::All.keeps("user.email"));
+    }
+
+    #[test]
+    fn test_span_name_rules() {
+        let processor = SpanNameProcessor::new(
+            (),
+            vec![
+                SpanNameRule {
+                    pattern: "/[0-9]+(/|$)".to_string(),
+                    replacement: "/{id}$1".to_string(),
+                },
+                SpanNameRule {
+                    pattern: "^GET ".to_string(),
+                    replacement: "get ".to_string(),
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            processor.rename("GET /users/12345/posts/6").as_deref(),
+            Some("get /users/{id}/posts/{id}")
+        );
+        assert_eq!(processor.rename("POST /users"), None);
+    }
+
+    #[test]
+    fn test_invalid_span_name_rules() {
+        assert!(parse_rules(r#"{"pattern": "x"}"#).is_empty());
+
+        let rules = parse_rules(
+            r#"[
+                {"pattern": "(", "replacement": ""},
+                {"pattern": "[0-9]+", "replacement": "{id}"}
+            ]"#,
+        );
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].0.as_str(), "[0-9]+");
+    }
+}
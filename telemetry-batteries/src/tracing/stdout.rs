@@ -1,18 +1,88 @@
-use crate::tracing::layers::stdout::stdout_layer;
+use crate::tracing::layers::compact::compact_layer;
+use crate::tracing::layers::error_metrics::error_metrics_layer;
+use crate::tracing::layers::stdout::{
+    stdout_layer_with_span_events, ColorMode, SpanEvents,
+};
 use crate::tracing::TracingShutdownHandle;
+use serde::{Deserialize, Serialize};
 use tracing_subscriber::{
     layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
 };
 
 pub struct StdoutBattery;
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StdoutConfig {
+    // Whether to write ANSI colors, `auto` by default.
+    pub color: ColorMode,
+    // Output format, `pretty` by default.
+    pub format: StdoutFormat,
+    // Span fields appended to each line by the `compact` format.
+    pub span_fields: Vec<String>,
+    // Span lifecycle events written by the `pretty` format, `none` by
+    // default.
+    pub span_events: SpanEvents,
+    // Whether the `pretty` format writes timestamps and span durations.
+    pub span_timing: bool,
+}
+
+impl Default for StdoutConfig {
+    fn default() -> Self {
+        Self {
+            color: ColorMode::default(),
+            format: StdoutFormat::default(),
+            span_fields: Vec::new(),
+            span_events: SpanEvents::default(),
+            span_timing: true,
+        }
+    }
+}
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum StdoutFormat {
+    /// Multi-line output with the source location of each event.
+    #[default]
+    Pretty,
+    /// One `key=value` line per event, with the Datadog trace ids, see
+    /// [`compact_layer`].
+    Compact,
+}
+
 impl StdoutBattery {
+    /// Installs the global subscriber. Records of the `log` crate, e.g. from
+    /// dependencies, are forwarded through the same filter and layers.
     pub fn init() -> TracingShutdownHandle {
-        let stdout_layer = stdout_layer();
-        let layers = EnvFilter::from_default_env().and_then(stdout_layer);
-        tracing_subscriber::registry().with(layers).init();
+        Self::init_with_config(StdoutConfig::default())
+    }
+
+    pub fn init_with_config(config: StdoutConfig) -> TracingShutdownHandle {
+        let layers =
+            EnvFilter::from_default_env().and_then(error_metrics_layer());
+
+        match config.format {
+            StdoutFormat::Pretty => {
+                let stdout_layer = stdout_layer_with_span_events(
+                    config.color,
+                    config.span_events,
+                    config.span_timing,
+                );
+                tracing_subscriber::registry()
+                    .with(layers.and_then(stdout_layer))
+                    .init();
+            }
+            StdoutFormat::Compact => {
+                let compact_layer = compact_layer(config.span_fields);
+                tracing_subscriber::registry()
+                    .with(layers.and_then(compact_layer))
+                    .init();
+            }
+        }
 
-        TracingShutdownHandle
+        TracingShutdownHandle::default()
     }
 }
 
@@ -0,0 +1,253 @@
+//! Event timestamps for the JSON formats, formatted once per tick of their
+//! precision instead of once per event.
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampPrecision {
+    Seconds,
+    Millis,
+    Micros,
+
+    /// Formats the exact time of every event, with nanoseconds. The default,
+    /// matching chrono's `to_rfc3339`.
+    #[default]
+    Exact,
+}
+
+struct CachedTimestamp {
+    precision: TimestampPrecision,
+    tick: (u64, u32),
+    formatted: String,
+}
+
+thread_local! {
+    static CACHED_TIMESTAMP: RefCell<Option<CachedTimestamp>> =
+        const { RefCell::new(None) };
+}
+
+impl TimestampPrecision {
+    /// Calls `f` with the current time as RFC 3339.
+    ///
+    /// Except for [`TimestampPrecision::Exact`], the formatted time is cached
+    /// per thread and only formatted again once the clock moves to the next
+    /// second, millisecond or microsecond.
+    pub fn with_now<R>(self, f: impl FnOnce(&str) -> R) -> R {
+        self.with_time(UtcTime::now(), f)
+    }
+
+    fn with_time<R>(self, now: UtcTime, f: impl FnOnce(&str) -> R) -> R {
+        let (digits, nanos_per_tick) = match self {
+            Self::Seconds => (0, 1_000_000_000),
+            Self::Millis => (3, 1_000_000),
+            Self::Micros => (6, 1_000),
+            Self::Exact => return f(&now.to_rfc3339(now.exact_digits())),
+        };
+
+        let tick = (now.unix_seconds, now.nanos / nanos_per_tick);
+
+        CACHED_TIMESTAMP.with(|cached| {
+            let Ok(mut cached) = cached.try_borrow_mut() else {
+                return f(&now.to_rfc3339(digits));
+            };
+
+            let fresh = cached.as_ref().is_some_and(|cached| {
+                cached.precision == self && cached.tick == tick
+            });
+            if !fresh {
+                *cached = Some(CachedTimestamp {
+                    precision: self,
+                    tick,
+                    formatted: now.to_rfc3339(digits),
+                });
+            }
+
+            let cached = cached.as_ref().expect("timestamp was just cached");
+            f(&cached.formatted)
+        })
+    }
+}
+
+/// A point in time broken down into its UTC calendar date and time of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UtcTime {
+    unix_seconds: u64,
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+    pub(crate) hour: u32,
+    pub(crate) minute: u32,
+    pub(crate) second: u32,
+    pub(crate) nanos: u32,
+}
+
+impl UtcTime {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn now() -> Self {
+        Self::from_system_time(SystemTime::now())
+    }
+
+    /// `SystemTime::now` panics in the browser, the time comes from `Date`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn now() -> Self {
+        let millis = js_sys::Date::now();
+        Self::from_system_time(
+            UNIX_EPOCH + std::time::Duration::from_secs_f64(millis / 1000.0),
+        )
+    }
+
+    pub(crate) fn from_system_time(time: SystemTime) -> Self {
+        // Times before the epoch only come from a broken clock.
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let unix_seconds = since_epoch.as_secs();
+
+        let days = (unix_seconds / 86_400) as i64;
+        let seconds_of_day = (unix_seconds % 86_400) as u32;
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            unix_seconds,
+            year,
+            month,
+            day,
+            hour: seconds_of_day / 3_600,
+            minute: seconds_of_day % 3_600 / 60,
+            second: seconds_of_day % 60,
+            nanos: since_epoch.subsec_nanos(),
+        }
+    }
+
+    /// Formats the time as RFC 3339 with `digits` fractional digits, e.g.
+    /// `2024-01-01T00:00:00.000+00:00` with 3.
+    pub(crate) fn to_rfc3339(self, digits: usize) -> String {
+        let mut formatted = String::with_capacity(36);
+        let _ = write!(
+            formatted,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second
+        );
+        if digits > 0 {
+            let fraction = format!("{:09}", self.nanos);
+            let _ = write!(formatted, ".{}", &fraction[..digits.min(9)]);
+        }
+        formatted.push_str("+00:00");
+
+        formatted
+    }
+
+    /// The fewest of 0, 3, 6 or 9 fractional digits representing the time
+    /// exactly.
+    pub(crate) fn exact_digits(self) -> usize {
+        match self.nanos {
+            0 => 0,
+            nanos if nanos % 1_000_000 == 0 => 3,
+            nanos if nanos % 1_000 == 0 => 6,
+            _ => 9,
+        }
+    }
+}
+
+/// Converts days since 1970-01-01 to a `(year, month, day)` date, see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use std::time::Duration;
+
+    #[test]
+    fn test_precision() {
+        let time = UtcTime::from_system_time(
+            UNIX_EPOCH + Duration::new(1_704_067_200, 123_456_789),
+        );
+        let format = |precision: TimestampPrecision| {
+            precision.with_time(time, str::to_string)
+        };
+
+        assert_eq!(
+            format(TimestampPrecision::Seconds),
+            "2024-01-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            format(TimestampPrecision::Millis),
+            "2024-01-01T00:00:00.123+00:00"
+        );
+        assert_eq!(
+            format(TimestampPrecision::Micros),
+            "2024-01-01T00:00:00.123456+00:00"
+        );
+        assert_eq!(
+            format(TimestampPrecision::default()),
+            "2024-01-01T00:00:00.123456789+00:00"
+        );
+    }
+
+    #[test]
+    fn test_matches_chrono() {
+        let formats = [
+            (0, SecondsFormat::Secs),
+            (3, SecondsFormat::Millis),
+            (6, SecondsFormat::Micros),
+            (9, SecondsFormat::Nanos),
+        ];
+
+        // Around leap days, the turn of centuries and far in the future.
+        for seconds in [
+            0,
+            951_782_400,
+            951_868_799,
+            1_709_164_800,
+            4_107_542_399,
+            253_402_300_799,
+        ] {
+            for nanos in [0, 1_000_000, 123_456_789] {
+                let time = UNIX_EPOCH + Duration::new(seconds, nanos);
+                let utc_time = UtcTime::from_system_time(time);
+                let chrono_time = DateTime::<Utc>::from(time);
+
+                for (digits, format) in formats {
+                    assert_eq!(
+                        utc_time.to_rfc3339(digits),
+                        chrono_time.to_rfc3339_opts(format, false)
+                    );
+                }
+                assert_eq!(
+                    utc_time.to_rfc3339(utc_time.exact_digits()),
+                    chrono_time.to_rfc3339()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! The spans of an application instrumented with the battery, as received
+//! by the agent.
+
+use std::time::Duration;
+
+use telemetry_batteries::testing::mock_agent::MockDatadogAgent;
+use telemetry_batteries::tracing::datadog::DatadogBattery;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_exports_spans_to_agent() {
+    let agent = MockDatadogAgent::start().unwrap();
+    let shutdown_handle = DatadogBattery::init(
+        Some(agent.endpoint()),
+        "test-service",
+        None,
+        false,
+    );
+
+    // Without `RUST_LOG` only errors pass the filter.
+    tracing::error_span!("parent", user.id = 42).in_scope(|| {
+        tracing::error_span!("child").in_scope(|| {});
+    });
+    assert!(shutdown_handle.flush(Duration::from_secs(5)));
+
+    let spans = agent.wait_for_spans(2, Duration::from_secs(5));
+    assert_eq!(spans.len(), 2);
+
+    let span = |resource| {
+        spans
+            .iter()
+            .find(|span| span.resource == resource)
+            .unwrap_or_else(|| panic!("no {resource} span in {spans:?}"))
+    };
+    let parent = span("parent");
+    let child = span("child");
+
+    assert!(spans.iter().all(|span| span.service == "test-service"));
+    assert_eq!(parent.parent_id, 0);
+    assert_eq!(child.trace_id, parent.trace_id);
+    assert_eq!(child.parent_id, parent.span_id);
+    assert_eq!(parent.meta["user.id"], "42");
+    assert!(child.duration <= parent.duration);
+}
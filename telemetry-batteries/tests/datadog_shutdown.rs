@@ -0,0 +1,27 @@
+//! Spans still buffered by the batch processor reach the agent once the
+//! battery's shutdown handle is shut down.
+
+use telemetry_batteries::testing::mock_agent::MockDatadogAgent;
+use telemetry_batteries::tracing::datadog::DatadogBattery;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_shutdown_exports_pending_spans() {
+    let agent = MockDatadogAgent::start().unwrap();
+    let shutdown_handle = DatadogBattery::init(
+        Some(agent.endpoint()),
+        "test-service",
+        None,
+        false,
+    );
+
+    // Without `RUST_LOG` only errors pass the filter.
+    tracing::error_span!("pending").in_scope(|| {});
+    assert!(agent.received_spans().is_empty());
+
+    shutdown_handle.shutdown().await;
+
+    let spans = agent.received_spans();
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].resource, "pending");
+    assert_eq!(spans[0].service, "test-service");
+}
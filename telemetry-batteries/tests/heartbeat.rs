@@ -0,0 +1,41 @@
+//! The heartbeat as seen by a metrics recorder.
+
+use std::thread;
+use std::time::Duration;
+
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use telemetry_batteries::heartbeat::{HeartbeatBattery, HeartbeatConfig};
+
+fn heartbeats(handle: &PrometheusHandle) -> u64 {
+    handle
+        .render()
+        .lines()
+        .find_map(|line| line.strip_prefix("service_heartbeat "))
+        .map_or(0, |value| value.parse().unwrap())
+}
+
+#[test]
+fn test_heartbeat_until_dropped() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    metrics::set_global_recorder(recorder).unwrap();
+
+    let config = HeartbeatConfig {
+        interval: Duration::ZERO,
+    };
+    assert!(HeartbeatBattery::init(config).is_err());
+
+    let shutdown_handle = HeartbeatBattery::init(HeartbeatConfig {
+        interval: Duration::from_millis(10),
+    })
+    .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert!(heartbeats(&handle) > 0);
+
+    drop(shutdown_handle);
+    // Lets a heartbeat racing with the drop land.
+    thread::sleep(Duration::from_millis(50));
+    let stopped_at = heartbeats(&handle);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(heartbeats(&handle), stopped_at);
+}